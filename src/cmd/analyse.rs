@@ -0,0 +1,271 @@
+use clap::ArgMatches;
+use std::path::PathBuf;
+
+use rustygenmo::error::{self, Error};
+use rustygenmo::train::{analyse, authors, data, plot, stats, style};
+use rustygenmo::train::output::{self, Format, Table};
+
+use crate::cmd::number;
+
+pub fn analyse_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let first = number(args, "first", 0)?;
+    let last = number(args, "last", 0)?;
+    let format = output::parse_format(args.value_of("output").unwrap_or("text"))?;
+    let plot_to = args.value_of("plot");
+
+    // neither is a count or a distribution, see plot::from_table
+    match (plot_to, args.subcommand_name()) {
+        (Some(_), Some(name)) if name == "compare" || name == "authors" =>
+            return Err(Error::Parse(format!("{} results can't be plotted", name))),
+        _ => (),
+    }
+
+    // compare brings its own two corpora
+    if let Some(sub) = args.subcommand_matches("compare") {
+        return output::print(&compare(sub, first, last)?, format);
+    }
+
+    let inputs: Vec<&str> = match args.values_of("file") {
+        Some(v) => v.collect(),
+        None => return Err(Error::Parse("a file, directory or glob to analyse must be given with -f".to_string())),
+    };
+    let sources = data::sources(&inputs).map_err(error::io(&format!("Could not open {:?} for reading", inputs)))?;
+
+    // authors come from the crawler index, whichever source their files are in
+    if let Some(sub) = args.subcommand_matches("authors") {
+        let files: Vec<PathBuf> = sources.into_iter().flat_map(|s| s.files).collect();
+        return output::print(&authors(sub, &files, first, last)?, format);
+    }
+
+    // analyse each source on its own, or everything together
+    let by_source = args.is_present("by-source");
+    let sets: Vec<(String, Vec<PathBuf>)> = if by_source {
+        sources.into_iter().map(|s| (s.name, s.files)).collect()
+    } else {
+        vec![(inputs.join(" "), sources.into_iter().flat_map(|s| s.files).collect())]
+    };
+
+    let mut chart: Option<plot::Plot> = None;
+
+    let mut result: Option<Table> = None;
+    for (name, files) in sets.iter() {
+        // a plot ranks the whole table before cutting it down to first/last
+        let mut table = match plot_to {
+            Some(_) => analyse_files(args, files, 0, 0, format)?,
+            None => analyse_files(args, files, first, last, format)?,
+        };
+
+        // every source goes on the same chart as a series of its own
+        if plot_to.is_some() {
+            let p = plot::from_table(&table, name, first, last)?;
+            match chart.as_mut() {
+                Some(c) => c.merge(p)?,
+                None => chart = Some(p),
+            }
+            continue;
+        }
+
+        if format == Format::Text {
+            if by_source {
+                println!("== {} ({} files) ==", name, files.len());
+            }
+            output::print(&table, format)?;
+            continue;
+        }
+
+        // machine readable output is a single table with a column saying where each row came from
+        if by_source {
+            table.tag("source", name);
+        }
+        match result.as_mut() {
+            Some(r) => r.append(table),
+            None => result = Some(table),
+        }
+    }
+
+    if let (Some(c), Some(target)) = (chart, plot_to) {
+        // `term` draws in the terminal, anything else is a path to write an svg to
+        if target == "term" {
+            print!("{}", plot::terminal(&c));
+        } else {
+            plot::save(&c, target)?;
+            println!("wrote plot to {}", target);
+        }
+    }
+    if let Some(table) = result {
+        output::print(&table, format)?;
+    }
+    return Ok(());
+}
+
+fn analyse_files(args: &ArgMatches, files: &Vec<PathBuf>, first: usize, last: usize,
+                 format: Format) -> Result<Table, Error> {
+    let mut table = match args.subcommand() {
+        ("dump", _) => Table::from_kv(&["word", "count"], analyse::dump_cmd(files)),
+        ("words", _) => Table::from_kv(&["word", "count"], analyse::word_cmd(files)),
+        ("groups", _) => Table::from_kv(&["frequency", "words"], analyse::group_cmd(files)),
+        ("ngrams", Some(sub)) => {
+            let (min, max) = analyse::parse_range(sub.value_of("n").unwrap_or("2"))?;
+
+            // first/last apply to each n-gram length on its own
+            let mut table = Table::new(&["ngram", "n", "count"]);
+            for (n, ngrams) in analyse::ngram_cmd(files, min, max) {
+                let mut t = Table::new(&["ngram", "n", "count"]);
+                ngrams.into_iter().for_each(|(k, v)| t.push(vec![k.into(), n.into(), v.into()]));
+                t.truncate(first, last);
+                table.append(t);
+            }
+            return Ok(table);
+        }
+        ("collocations", Some(sub)) => {
+            let window = number(sub, "window", 1)?;
+            let min_count = number(sub, "min-count", 5)?;
+            let rank = sub.value_of("rank").unwrap_or("llr");
+
+            let mut table = Table::new(&["pair", "count", "pmi", "llr", "t"]);
+            analyse::collocation_cmd(files, window, min_count, rank).into_iter()
+                .for_each(|(k, c)| table.push(vec![k.into(), c.count.into(), c.pmi.into(), c.llr.into(), c.t.into()]));
+            table
+        }
+        ("stats", Some(sub)) => {
+            let window = number(sub, "window", stats::DEFAULT_WINDOW)?;
+            if window == 0 {
+                return Err(Error::Parse("bad window 0, the moving type-token ratio needs at least 1 word".to_string()));
+            }
+            let s = stats::stats_cmd(files, window);
+
+            // the vocabulary growth curve is a table of its own
+            if sub.is_present("curve") {
+                let mut table = Table::from_kv(&["tokens", "types"], s.curve);
+                table.truncate(first, last);
+                return Ok(table);
+            }
+
+            let mut table = Table::new(&["statistic", "value"]);
+            table.push(vec!["tokens".into(), s.tokens.into()]);
+            table.push(vec!["types".into(), s.types.into()]);
+            table.push(vec!["hapax_legomena".into(), s.hapax.into()]);
+            table.push(vec!["ttr".into(), s.ttr.into()]);
+            table.push(vec![format!("mattr_{}", s.window).into(), s.mattr.into()]);
+            table.push(vec!["yules_k".into(), s.yules_k.into()]);
+            table.push(vec!["zipf_exponent".into(), s.zipf_exponent.into()]);
+            table.push(vec!["zipf_r2".into(), s.zipf_r2.into()]);
+            table.push(vec!["heaps_k".into(), s.heaps_k.into()]);
+            table.push(vec!["heaps_beta".into(), s.heaps_beta.into()]);
+            table.push(vec!["heaps_r2".into(), s.heaps_r2.into()]);
+            return Ok(table);
+        }
+        ("concordance", Some(sub)) => {
+            let width = number(sub, "width", 40)?;
+
+            let mut table = Table::new(&["file", "offset", "left", "keyword", "right"]);
+            for c in analyse::concordance_cmd(files, sub.value_of("phrase").unwrap_or_default(), width) {
+                // line the keywords up when reading in a terminal
+                let left = if format == Format::Text {
+                    format!("{:>width$}", c.left, width = width)
+                } else {
+                    c.left
+                };
+                table.push(vec![c.file.to_string_lossy().to_string().into(), c.offset.into(), left.into(),
+                                c.keyword.into(), c.right.into()]);
+            }
+            table
+        }
+        ("style", Some(sub)) => {
+            let s = style::style_cmd(files);
+
+            let mut table = match sub.value_of("distribution") {
+                Some("sentence") => Table::from_kv(&["words", "sentences"], s.sentence_lengths.into_iter().collect()),
+                Some("word") => Table::from_kv(&["characters", "words"], s.word_lengths.into_iter().collect()),
+                Some("punctuation") => Table::from_kv(&["mark", "per_1000_words"], s.punctuation_rate()),
+                _ => {
+                    let mut table = Table::new(&["statistic", "value"]);
+                    table.push(vec!["sentences".into(), s.sentences.into()]);
+                    table.push(vec!["words".into(), s.words.into()]);
+                    table.push(vec!["mean_sentence_length".into(), s.mean_sentence().into()]);
+                    table.push(vec!["mean_word_length".into(), s.mean_word().into()]);
+                    table.push(vec!["flesch_reading_ease".into(), s.flesch_reading_ease().into()]);
+                    table.push(vec!["flesch_kincaid_grade".into(), s.flesch_kincaid_grade().into()]);
+                    table.push(vec!["gunning_fog".into(), s.gunning_fog().into()]);
+                    table.push(vec!["dialogue_ratio".into(), s.dialogue_ratio().into()]);
+                    s.punctuation_rate().into_iter()
+                        .for_each(|(p, r)| table.push(vec![format!("punctuation {}", p).into(), r.into()]));
+                    table
+                }
+            };
+            table.truncate(first, last);
+            return Ok(table);
+        }
+        _ => return Err(Error::Parse("One of dump|words|groups|ngrams|collocations|stats|concordance|compare|style|authors must be chosen".to_string())),
+    };
+
+    table.truncate(first, last);
+    return Ok(table);
+}
+
+fn compare(args: &ArgMatches, first: usize, last: usize) -> Result<Table, Error> {
+    let corpus = |name: &str| -> Result<Vec<PathBuf>, Error> {
+        let input = args.value_of(name).unwrap_or_default();
+        let sources = data::sources(&vec![input]).map_err(error::io(&format!("Could not open {} for reading", input)))?;
+        return Ok(sources.into_iter().flat_map(|s| s.files).collect());
+    };
+    let (a, b) = (corpus("a")?, corpus("b")?);
+
+    let (min, max) = analyse::parse_range(args.value_of("n").unwrap_or("1"))?;
+    let min_count = number(args, "min-count", 5)?;
+    let min_ll = number(args, "min-ll", 3.84)?;
+
+    let mut table = Table::new(&["item", "n", "freq_a", "freq_b", "ll", "pct_diff", "overused_in"]);
+    for k in analyse::keyness_cmd(&a, &b, min, max, min_count, min_ll) {
+        let overused = if k.pct_diff > 0.0 { "a" } else { "b" };
+        table.push(vec![k.item.into(), k.n.into(), k.a.into(), k.b.into(), k.ll.into(), k.pct_diff.into(),
+                        overused.into()]);
+    }
+
+    table.truncate(first, last);
+    return Ok(table);
+}
+
+fn authors(args: &ArgMatches, files: &Vec<PathBuf>, first: usize, last: usize) -> Result<Table, Error> {
+    let features = number(args, "features", authors::DEFAULT_FEATURES)?;
+    let min_words = number(args, "min-words", authors::DEFAULT_MIN_WORDS)?;
+    let unknown = match args.value_of("unknown") {
+        Some(input) => {
+            let sources = data::sources(&vec![input]).map_err(error::io(&format!("Could not open {} for reading", input)))?;
+            Some((input, sources.into_iter().flat_map(|s| s.files).collect::<Vec<PathBuf>>()))
+        }
+        None => None,
+    };
+
+    let by = data::authors(files);
+    if by.is_empty() {
+        return Err(Error::Parse("no authors found, analyse authors needs a crawler store with an index".to_string()));
+    }
+    let result = authors::authors_cmd(files, &by, unknown.as_ref().map(|(n, f)| (*n, f)), features, min_words)?;
+
+    let mut table = if args.is_present("profiles") {
+        let mut table = Table::new(&["author", "word", "per_1000_words", "z"]);
+        for p in result.profiles.iter().chain(result.unknown.iter()) {
+            for (w, z) in result.features.iter().zip(p.z.iter()) {
+                table.push(vec![p.name.as_str().into(), w.as_str().into(), p.rate(w).into(), (*z).into()]);
+            }
+        }
+        table
+    } else if result.unknown.is_some() {
+        let mut table = Table::new(&["author", "files", "words", "delta", "cosine"]);
+        for d in result.distances.iter() {
+            if let Some(p) = result.profiles.iter().find(|p| p.name == d.b) {
+                table.push(vec![d.b.as_str().into(), p.files.into(), p.words.into(), d.delta.into(), d.cosine.into()]);
+            }
+        }
+        table
+    } else {
+        let mut table = Table::new(&["a", "b", "delta", "cosine"]);
+        result.distances.iter()
+            .for_each(|d| table.push(vec![d.a.as_str().into(), d.b.as_str().into(), d.delta.into(), d.cosine.into()]));
+        table
+    };
+
+    table.truncate(first, last);
+    return Ok(table);
+}
@@ -0,0 +1,18 @@
+use clap::ArgMatches;
+
+use rustygenmo::crawl::{crawler, Site};
+use rustygenmo::error::Error;
+
+pub fn crawl_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let url = args.value_of("seed").unwrap_or_default();
+    let path = args.value_of("path").unwrap_or_default();
+
+    let site = if args.is_present("fanfiction") {
+        Site::FanFiction
+    } else if args.is_present("dailymail") {
+        Site::DailyMail
+    } else {
+        return Err(Error::Parse("you must choose one of [fanfiction|dailymail]".to_string()));
+    };
+    return crawler::new(site, url, path).crawl();
+}
@@ -0,0 +1,70 @@
+use clap::ArgMatches;
+use std::fs;
+
+use rustygenmo::error::{self, Error};
+use rustygenmo::generate::{generator, Switch};
+use rustygenmo::model::{self, smoothing, Model};
+
+use crate::cmd::number;
+
+pub fn run_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let db_paths: Vec<&str> = match args.values_of("dbpath") {
+        Some(v) => v.collect(),
+        None => vec!["test"],
+    };
+    let count = number(args, "length", 10)?;
+    let switch = match args.value_of("switch") {
+        Some("sentence") => Switch::Sentence,
+        Some("paragraph") => Switch::Paragraph,
+        Some("word") | None => Switch::Word,
+        Some(v) => return Err(Error::Parse(format!("unknown switch {}, expected one of word|sentence|paragraph", v))),
+    };
+
+    // one weight per db, equal weights if none are given
+    let weights: Vec<f64> = match args.values_of("weight") {
+        Some(v) => v.map(|w| w.parse::<f64>().map_err(|e| Error::Parse(format!("bad weight {}: {}", w, e))))
+            .collect::<Result<Vec<f64>, Error>>()?,
+        None => vec![1.0; db_paths.len()],
+    };
+    if weights.len() != db_paths.len() {
+        return Err(Error::Parse(format!("got {} weights for {} dbs", weights.len(), db_paths.len())));
+    }
+
+    let smoothing = args.value_of("smoothing").map(smoothing::parse).transpose()?;
+    let mut models: Vec<Model> = Vec::new();
+    for path in db_paths.iter() {
+        let m = model::open(path)?;
+        models.push(match smoothing {
+            Some(s) => m.smoothing(s)?,
+            None => m,
+        });
+    }
+    let unk_model = args.value_of("unk-model").map(model::open).transpose()?;
+
+    let mut generator = generator::new().length(count).switch(switch);
+    for (m, w) in models.iter().zip(weights.into_iter()) {
+        generator = generator.model(m, w);
+    }
+    if let Some(m) = unk_model.as_ref() {
+        generator = generator.unk_model(m);
+    }
+    if let Some(e) = args.value_of("end") {
+        generator = generator.end_with(e);
+    } else if let Some(k) = args.value_of("keyword") {
+        generator = generator.keyword(k);
+    }
+
+    let generated = generator.generate()?;
+    for (path, contexts, largest_n, smoothing) in generated.models.iter() {
+        println!("{}: contexts: {}\n largest n: {}\n smoothing: {}", path, contexts, largest_n, smoothing);
+    }
+    println!("output:\n\n{}", generated.text);
+    return save(args.value_of("output"), &generated.text);
+}
+
+fn save(path: Option<&str>, text: &str) -> Result<(), Error> {
+    if let Some(p) = path {
+        fs::write(p, text).map_err(error::io(&format!("failed to write output to {}", p)))?;
+    }
+    return Ok(());
+}
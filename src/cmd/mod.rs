@@ -0,0 +1,20 @@
+// The subcommands: each reads its arguments and hands them to the library.
+use clap::ArgMatches;
+use std::fmt;
+use std::str::FromStr;
+
+use rustygenmo::error::Error;
+
+pub mod analyse;
+pub mod crawl;
+pub mod generate;
+pub mod model;
+pub mod train;
+
+// the number given for option /name/, /default/ if it wasn't
+pub fn number<T: FromStr>(args: &ArgMatches, name: &str, default: T) -> Result<T, Error> where T::Err: fmt::Display {
+    return match args.value_of(name) {
+        Some(v) => v.parse::<T>().map_err(|e| Error::Parse(format!("bad value {} for {}: {}", v, name, e))),
+        None => Ok(default),
+    };
+}
@@ -0,0 +1,96 @@
+use clap::ArgMatches;
+use std::path::{Path, PathBuf};
+
+use rustygenmo::error::{self, Error};
+use rustygenmo::model::{self, get_meta, smoothing, Model, REVERSE};
+use rustygenmo::model::evaluate::Report;
+use rustygenmo::model::prune::Pruned;
+use rustygenmo::train::data;
+
+pub fn export_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let output = args.value_of("output").unwrap_or_default();
+    let model = open_arg(args)?;
+
+    let order = model.export(output)?;
+    println!("wrote {}-gram {} model to {}", order, model.smoothed_with(), output);
+    return Ok(());
+}
+
+pub fn compile_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let output = args.value_of("output").unwrap_or_default();
+    let model = open_arg(args)?;
+
+    let order = model.compile(output)?;
+    println!("compiled {}-gram {} model{} to {}", order, model.smoothed_with(),
+             if get_meta::<bool>(model.meta(), REVERSE)? == Some(true) { " with reverse chains" } else { "" }, output);
+    return Ok(());
+}
+
+pub fn evaluate_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let model = open_arg(args)?;
+
+    // without a path, fall back to whatever `train --holdout` put aside
+    let files = match args.value_of("path") {
+        Some(path) => data::walk(Path::new(path)).map_err(error::io(&format!("couldnt open dir {}", path)))?,
+        None => match model.held_out()? {
+            Some(fs) => fs,
+            None => return Err(Error::Model(format!("db {} has no held out files, pass a directory with -p",
+                                                    model.path()))),
+        },
+    };
+
+    let report = model.evaluate(&files)?;
+    println!("smoothing: {}", model.smoothed_with());
+    print_report(&report);
+    return Ok(());
+}
+
+fn print_report(report: &Report) -> () {
+    println!("tokens: {}", report.tokens);
+    println!("out of vocabulary: {} ({:.2}%)", report.oov, report.oov_rate() * 100.0);
+    println!("log10 likelihood: {:.4}", report.log_likelihood);
+    println!("per token log10 likelihood: {:.4}", report.per_token());
+    println!("perplexity: {:.4}", report.perplexity());
+}
+
+pub fn prune_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let min_count: u32 = args.value_of("min_count").unwrap_or("1").parse()
+        .map_err(|e| Error::Parse(format!("bad --min-count: {}", e)))?;
+    let threshold: Option<f64> = args.value_of("entropy").map(|t| t.parse::<f64>()).transpose()
+        .map_err(|e| Error::Parse(format!("bad --entropy: {}", e)))?;
+    if min_count <= 1 && threshold.is_none() {
+        return Err(Error::Parse("nothing to prune, give --min-count above 1 or --entropy".to_string()));
+    }
+
+    let model = open_arg(args)?;
+
+    // perplexity is only reported when there's something held out to score
+    let files: Option<Vec<PathBuf>> = match args.value_of("path") {
+        Some(path) => Some(data::walk(Path::new(path)).map_err(error::io(&format!("couldnt open dir {}", path)))?),
+        None => model.held_out()?,
+    };
+    let scored = files.as_ref().map(|fs| model.perplexity(fs)).transpose()?;
+
+    let (pruned, model) = model.prune(min_count, threshold)?;
+    print_pruned(&pruned);
+
+    if let (Some(files), Some(before)) = (files, scored) {
+        println!("perplexity ({}): {:.4} -> {:.4}", model.smoothed_with(), before, model.perplexity(&files)?);
+    }
+    return Ok(());
+}
+
+// the model given with -d, smoothed as asked with -s
+fn open_arg(args: &ArgMatches) -> Result<Model, Error> {
+    let model = model::open(args.value_of("dbpath").unwrap_or_default())?;
+    return match args.value_of("smoothing") {
+        Some(s) => model.smoothing(smoothing::parse(s)?),
+        None => Ok(model),
+    };
+}
+
+fn print_pruned(pruned: &Pruned) -> () {
+    println!("removed {} of {} n-grams", pruned.ngrams_removed, pruned.ngrams);
+    println!("removed {} of {} contexts", pruned.contexts_removed, pruned.contexts);
+    println!("size: {} -> {} bytes", pruned.size.0, pruned.size.1);
+}
@@ -0,0 +1,130 @@
+use clap::ArgMatches;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rustygenmo::error::{self, Error};
+use rustygenmo::model::{self, smoothing, store};
+use rustygenmo::train::{self, analyse, bpe, data, grouping, trainer, DEFAULT_CHAR_ORDER};
+use rustygenmo::train::output::{self, Format, Table};
+
+use crate::cmd::number;
+
+// print the word -> group map a db was trained with
+pub fn groups_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let db_path = args.value_of("dbpath").unwrap_or("test");
+    let format = output::parse_format(args.value_of("output").unwrap_or("text"))?;
+    let only = args.value_of("group").map(|g| g.parse::<u32>())
+        .transpose()
+        .map_err(|e| Error::Parse(format!("bad group: {}", e)))?;
+
+    let chain = train::open(db_path)?;
+
+    let map: Vec<(String, u32)> = chain.group_map()?.into_iter()
+        .filter(|(_, g)| only.map(|o| o == *g).unwrap_or(true))
+        .collect();
+
+    if args.is_present("summary") || format == Format::Text {
+        let (grouping, scope) = chain.grouping()?;
+        eprintln!("grouping: {}, scope: {}", grouping.unwrap_or("unknown".to_string()),
+                  scope.unwrap_or("unknown".to_string()));
+    }
+    if args.is_present("summary") {
+        let mut sizes: BTreeMap<u32, u64> = BTreeMap::new();
+        map.iter().for_each(|(_, g)| *sizes.entry(*g).or_insert(0) += 1);
+        return output::print(&Table::from_kv(&["group", "words"], sizes.into_iter().collect()), format);
+    }
+
+    return output::print(&Table::from_kv(&["word", "group"], map), format);
+}
+
+pub fn tokenizer_cmd(args: &ArgMatches) -> Result<(), Error> {
+    return match args.subcommand() {
+        ("learn", Some(sub)) => {
+            let path = sub.value_of("path").unwrap_or_default();
+            let db_path = sub.value_of("dbpath").unwrap_or("test");
+            let merges = number(sub, "merges", bpe::DEFAULT_MERGES)?;
+
+            let files = data::walk(Path::new(path)).map_err(error::io(&format!("couldnt open dir {}", path)))?;
+
+            let b = bpe::learn(&files, merges);
+            let mut chain = train::new(db_path)?;
+            chain.bpe(&b)?;
+            println!("learned {} merges from {} files into {}, train on it to use them", b.merges.len(),
+                     files.len(), db_path);
+            Ok(())
+        }
+        ("encode", Some(sub)) => {
+            let db_path = sub.value_of("dbpath").unwrap_or("test");
+            let db = store::open(db_path)?;
+            match model::load_bpe(&db)? {
+                Some(b) => {
+                    println!("{}", b.encode_words(&analyse::create_corpus(sub.value_of("text").unwrap_or_default())).join(" "));
+                    Ok(())
+                }
+                None => Err(Error::Model(format!("db {} has no tokenizer, learn one with `tokenizer learn`", db_path))),
+            }
+        }
+        _ => Err(Error::Parse("One of learn|encode must be chosen".to_string())),
+    };
+}
+
+pub fn train_cmd(args: &ArgMatches) -> Result<(), Error> {
+    let path = args.value_of("path").unwrap_or_default();
+    let mut trainer = trainer::new(path);
+
+    // with --memory nothing is written, for trying out settings against the held out files
+    if !args.is_present("memory") {
+        trainer = trainer.db(args.value_of("dbpath").unwrap_or("test"));
+    }
+    if args.value_of("unit") == Some("char") {
+        trainer = trainer.chars(number(args, "order", DEFAULT_CHAR_ORDER)?);
+    } else if args.is_present("order") {
+        return Err(Error::Parse("--order is for --unit char models, word models are grouped instead".to_string()));
+    }
+
+    // -c on its own keeps the original top-n grouping
+    match (args.value_of("grouping"), args.value_of("count")) {
+        (Some(v), _) => trainer = trainer.grouping(grouping::parse(v)?),
+        (None, Some(c)) => trainer = trainer.grouping(grouping::parse(&format!("top:{}", c))?),
+        (None, None) => (),
+    }
+    if args.is_present("max-order") {
+        trainer = trainer.max_order(number(args, "max-order", 0)?);
+    }
+
+    if let Some(v) = args.value_of("smoothing") {
+        trainer = trainer.smoothing(smoothing::parse(v)?);
+    }
+    if args.is_present("holdout") {
+        trainer = trainer.holdout(number(args, "holdout", 0.0)?);
+    }
+    trainer = trainer.scope(grouping::parse_scope(args.value_of("group-scope").unwrap_or("corpus"))?);
+
+    if args.is_present("min-count") || args.is_present("max-vocab") {
+        let min_count = number(args, "min-count", 1)?;
+        let max_vocab = args.value_of("max-vocab").map(|v| v.parse::<usize>())
+            .transpose()
+            .map_err(|e| Error::Parse(format!("bad value for max-vocab: {}", e)))?;
+        trainer = trainer.vocabulary(min_count, max_vocab, args.is_present("unk-classes"));
+    }
+    if args.is_present("reverse") {
+        trainer = trainer.reverse();
+    }
+
+    let chain = trainer.train()?;
+    let summary = chain.summary();
+    let held = chain.held_out()?;
+    if !held.is_empty() {
+        println!("held out {} of {} files", held.len(), held.len() + summary.files);
+    }
+    if let Some((kept, seen)) = summary.vocabulary {
+        println!("kept {} of {} words", kept, seen);
+    }
+    summary.groups.iter().for_each(|(g, n)| println!("group {}: {} words", g, n));
+
+    // an in-memory model is gone once we return, so score it now
+    if args.is_present("memory") && !held.is_empty() {
+        println!("held out perplexity: {:.4}", chain.perplexity(&held)?);
+    }
+    return Ok(());
+}
@@ -0,0 +1,36 @@
+use crate::crawl::{dailymail, fanfiction, store};
+use crate::error::Error;
+
+// sites there's a crawler for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Site {
+    FanFiction,
+    DailyMail,
+}
+
+// Crawls a site from a seed url, saving what it finds as text files under a directory to train on.
+// See store::Store for how the files are named and indexed.
+pub struct Crawler {
+    site: Site,
+    seed: String,
+    path: String,
+}
+
+pub fn new(site: Site, seed: &str, path: &str) -> Crawler {
+    return Crawler {
+        site,
+        seed: seed.to_string(),
+        path: path.to_string(),
+    };
+}
+
+impl Crawler {
+    // runs until the site has nothing more to give
+    pub fn crawl(&self) -> Result<(), Error> {
+        let store = store::new(&self.path);
+        return match self.site {
+            Site::FanFiction => fanfiction::crawl(&self.seed, store),
+            Site::DailyMail => dailymail::crawl(&self.seed, store),
+        };
+    }
+}
@@ -9,10 +9,11 @@ use scraper::{ElementRef, Html, Selector};
 use url::Url;
 
 use crate::crawl::{pool, store};
-use crate::crawl::fanfiction::base_url;
+use crate::crawl::fanfiction::{base_url, fetch, selector, unexpected};
 use crate::crawl::store::Chapter;
+use crate::error::Error;
 
-use self::isahc::{HttpClient, ResponseExt};
+use self::isahc::HttpClient;
 
 pub struct DailyMail {
     client: HttpClient,
@@ -37,149 +38,132 @@ pub struct DailyMail {
     title_sel: Selector,
     body_sel: Selector,
     p_sel: Selector,
+    author_sel: Selector,
 }
 
 // single threaded
-pub fn crawl(seed: &str, store: store::Store) -> () {
-    let processor = DailyMail::new(store);
-    let articles = processor.crawl_archive(&seed.to_string()).into_iter()
-        .flat_map(|m| processor.crawl_month(&m)).into_iter()
-        .flat_map(|d| processor.crawl_day(&d)).into_iter()
+pub fn crawl(seed: &str, store: store::Store) -> Result<(), Error> {
+    let processor = DailyMail::new(store)?;
+    let articles = processor.crawl_archive(&seed.to_string())?.into_iter()
+        .flat_map(|m| skip(&m, processor.crawl_month(&m))).into_iter()
+        .flat_map(|d| skip(&d, processor.crawl_day(&d))).into_iter()
         .enumerate()
         .for_each(|(i, u)| {
             if i % 100 == 0 {
                 thread::sleep(Duration::from_secs(2));
             }
-            processor.crawl_article(&u);
+            if let Err(e) = processor.crawl_article(&u) {
+                eprintln!("skipping {}: {}", u, e);
+            }
         });
+    return Ok(());
+}
+
+// the links found on a page, or none if it couldn't be crawled
+fn skip(url: &str, links: Result<Vec<String>, Error>) -> Vec<String> {
+    return links.unwrap_or_else(|e| {
+        eprintln!("skipping {}: {}", url, e);
+        Vec::new()
+    });
 }
 
 impl pool::Processor for DailyMail {
-    fn crawl(&self, url: String) {
-        self.crawl_article(&url);
+    fn crawl(&self, url: String) -> Result<(), Error> {
+        return self.crawl_article(&url);
     }
 }
 
 impl DailyMail {
-    pub fn new(store: store::Store) -> DailyMail {
-        return DailyMail {
-            client: HttpClient::new().unwrap(),
+    pub fn new(store: store::Store) -> Result<DailyMail, Error> {
+        return Ok(DailyMail {
+            client: HttpClient::new()?,
             store,
 
-            link_sel: Selector::parse("a").unwrap(),
-            year_sel: Selector::parse("ul.archive-index.home.link-box li").unwrap(),
-            month_sel: Selector::parse("ul.cleared li").unwrap(),
+            link_sel: selector("a")?,
+            year_sel: selector("ul.archive-index.home.link-box li")?,
+            month_sel: selector("ul.cleared li")?,
 
-            day_sel: Selector::parse("div.debate.column-split.first-column").unwrap(),
+            day_sel: selector("div.debate.column-split.first-column")?,
 
-            content_sel: Selector::parse("div.alpha.debate.sitemap").unwrap(),
-            article_sel: Selector::parse("ul.archive-articles.debate.link-box").unwrap(),
+            content_sel: selector("div.alpha.debate.sitemap")?,
+            article_sel: selector("ul.archive-articles.debate.link-box")?,
 
-            article_content_sel: Selector::parse("#js-article-text").unwrap(),
-            title_sel: Selector::parse("h1,h2").unwrap(),
-            body_sel: Selector::parse(r#"div[itemprop="articleBody""#).unwrap(),
-            p_sel: Selector::parse("p").unwrap(),
-        };
+            article_content_sel: selector("#js-article-text")?,
+            title_sel: selector("h1,h2")?,
+            body_sel: selector(r#"div[itemprop="articleBody""#)?,
+            p_sel: selector("p")?,
+            author_sel: selector("a.author")?,
+        });
     }
 
 
     // return all monthly links in the archive page
-    fn crawl_archive(&self, url: &String) -> Vec<String> {
-        let mut result = self.client.get(url).unwrap();
-        if !result.status().is_success() {
-            eprintln!("request to {} resulted in {}", url, result.status());
-            return Vec::new();
-        }
-
-        let text = result.text().unwrap();
+    fn crawl_archive(&self, url: &String) -> Result<Vec<String>, Error> {
+        let text = fetch(&self.client, url)?;
         let doc = Html::parse_document(&text);
 
-        let base = base_url(&url).unwrap();
+        let base = base_url(&url)?;
 
         let mut links: Vec<String> = Vec::new();
 
-        let months = doc.select(&self.year_sel).next().unwrap();
+        let months = doc.select(&self.year_sel).next().ok_or_else(|| unexpected(url))?;
         for month in months.select(&self.month_sel) {
             for link in month.select(&self.link_sel) {
-                links.push(make_link(&base, link));
+                links.extend(make_link(&base, link));
             }
         }
 
         println!("got month links {}", links.len());
-        return links;
+        return Ok(links);
     }
 
-    fn crawl_month(&self, url: &String) -> Vec<String> {
-        let mut result = self.client.get(url).unwrap();
-        if !result.status().is_success() {
-            eprintln!("request to {} resulted in {}", url, result.status());
-            return Vec::new();
-        }
-
-        let text = result.text().unwrap();
+    fn crawl_month(&self, url: &String) -> Result<Vec<String>, Error> {
+        let text = fetch(&self.client, url)?;
         let doc = Html::parse_document(&text);
 
-        let base = base_url(&url).unwrap();
+        let base = base_url(&url)?;
 
         let mut links: Vec<String> = Vec::new();
         doc.select(&self.day_sel).map(|d| d.select(&self.link_sel))
             .flat_map(|x| x.into_iter())
-            .map(|l| make_link(&base, l))
+            .filter_map(|l| make_link(&base, l))
             .for_each(|l| links.push(l));
 
 
         println!("got day links {}", links.len());
-        return links;
+        return Ok(links);
     }
 
-    fn crawl_day(&self, url: &String) -> Vec<String> {
-        let mut result = self.client.get(url).unwrap();
-        if !result.status().is_success() {
-            eprintln!("request to crawl days {} resulted in {}", url, result.status());
-            return Vec::new();
-        }
-
-        let text = result.text().unwrap();
+    fn crawl_day(&self, url: &String) -> Result<Vec<String>, Error> {
+        let text = fetch(&self.client, url)?;
         let doc = Html::parse_document(&text);
 
-        let base = base_url(&url).unwrap();
+        let base = base_url(&url)?;
 
         let mut links: Vec<String> = Vec::new();
-        let content = doc.select(&self.content_sel).next().unwrap();
+        let content = doc.select(&self.content_sel).next().ok_or_else(|| unexpected(url))?;
 
         content.select(&self.article_sel).map(|a| a.select(&self.link_sel))
             .flat_map(|x| x.into_iter())
-            .map(|l| make_link(&base, l))
+            .filter_map(|l| make_link(&base, l))
             .for_each(|l| links.push(l));
 
         println!("got article links {}", links.len());
-        return links;
+        return Ok(links);
     }
 
-    fn crawl_article(&self, url: &String) -> Option<()> {
+    fn crawl_article(&self, url: &String) -> Result<(), Error> {
         println!("fetching {}", url);
-        let mut result = match self.client.get(url) {
-            Ok(r) => r,
-            Err(_) => return None,
-        };
-        if !result.status().is_success() {
-            eprintln!("request to crawl article {} resulted in {}", url, result.status());
-            return None;
-        }
-
-        let text = match result.text() {
-            Ok(t) => t,
-            Err(_) => return None,
-        };
+        let text = fetch(&self.client, url)?;
         let doc = Html::parse_document(&text);
 
-        let article = doc.select(&self.article_content_sel).next()?;
+        let article = doc.select(&self.article_content_sel).next().ok_or_else(|| unexpected(url))?;
         let mut title = match article.select(&self.title_sel).next() {
             Some(t) => t.inner_html(),
             None => url.replace("/", ""),
         };
 
-        let text: String = article.select(&self.body_sel).next()?
+        let text: String = article.select(&self.body_sel).next().ok_or_else(|| unexpected(url))?
             .select(&self.p_sel).into_iter()
             .flat_map(|x| x.text().into_iter())
             .fold(String::new(), |a, x| a + x);
@@ -190,15 +174,22 @@ impl DailyMail {
             title = title.split_whitespace().take(10).collect();
         }
 
+        // by-line, if there is one
+        let author = article.select(&self.author_sel).next()
+            .map(|a| a.text().collect::<String>().trim().to_string());
+
         let chapter = Chapter {
             title,
             text,
+            author,
+            url: url.to_string(),
         };
-        Some(self.store.save(chapter))
+        return self.store.save(chapter);
     }
 }
 
-fn make_link(base: &Url, link: ElementRef) -> String {
-    let url = link.value().attr("href").unwrap().to_string();
-    return base.join(&url).unwrap().to_string();
+// links without a usable href are left out
+fn make_link(base: &Url, link: ElementRef) -> Option<String> {
+    let url = link.value().attr("href")?;
+    return base.join(url).ok().map(|u| u.to_string());
 }
@@ -6,24 +6,35 @@ use url::Url;
 
 use crate::crawl::pool;
 use crate::crawl::store;
+use crate::error::Error;
 
 use self::isahc::{HttpClient, ResponseExt};
 use self::url::ParseError;
 use std::sync::Arc;
 
 // breadth first crawl
-pub fn crawl(seed: &str, store: store::Store) -> () {
+pub fn crawl(seed: &str, store: store::Store) -> Result<(), Error> {
     let threads: usize = 6;
 
     // create thread pool
-    let processor = Arc::new(FanFiction::new(store));
+    let processor = Arc::new(FanFiction::new(store)?);
 
     // iterate through listings in a genre to build a list of books. Just use 1 crawler for this
     let mut book_urls: Vec<String> = Vec::new();
 
-    let mut previous: String = "".parse().unwrap();
+    let mut previous: String = String::new();
     let mut next: String = seed.to_string();
-    while let Some(n) = processor.crawl_genre(&next, &mut book_urls) {
+    loop {
+        let n = match processor.crawl_genre(&next, &mut book_urls) {
+            Ok(Some(n)) => n,
+            Ok(None) => break,
+            Err(e) if book_urls.is_empty() => return Err(e),
+            // download what was found before the listing went wrong
+            Err(e) => {
+                eprintln!("stopped listing books at {}: {}", next, e);
+                break;
+            }
+        };
         if n == previous {
             println!("next {:?} previous {:?}", next, previous);
             break;
@@ -43,6 +54,7 @@ pub fn crawl(seed: &str, store: store::Store) -> () {
     println!("terminating crawlers");
     println!("{:?}", pool.len());
     pool.stop();
+    return Ok(());
 }
 
 struct FanFiction {
@@ -58,16 +70,17 @@ struct FanFiction {
 
     // Book page
     title_sel: Selector,
+    author_sel: Selector,
     next_sel: Selector,
     chapter_sel: Selector,
 }
 
 impl pool::Processor for FanFiction {
-    fn crawl(&self, url: String) {
-        let mut previous: String = "".parse().unwrap();
+    fn crawl(&self, url: String) -> Result<(), Error> {
+        let mut previous: String = String::new();
         let mut next: String = url;
 
-        while let Some(n) = self.crawl_chapter(&next) {
+        while let Some(n) = self.crawl_chapter(&next)? {
             if n == previous {
                 break;
             }
@@ -78,95 +91,112 @@ impl pool::Processor for FanFiction {
 //            println!("previous={} next={}", previous, next);
 //            break
         }
+        return Ok(());
     }
 }
 
 impl FanFiction {
-    pub fn new(store: store::Store) -> FanFiction {
-        return FanFiction {
-            client: HttpClient::new().unwrap(),
+    pub fn new(store: store::Store) -> Result<FanFiction, Error> {
+        return Ok(FanFiction {
+            client: HttpClient::new()?,
             store,
 
-            content_sel: Selector::parse(r#"#content_parent #content_wrapper #content_wrapper_inner"#).unwrap(),
-            books_sel: Selector::parse(r#"div.z-list.zhover.zpointer a.stitle"#).unwrap(),
-            link_sel: Selector::parse("center a").unwrap(),
+            content_sel: selector(r#"#content_parent #content_wrapper #content_wrapper_inner"#)?,
+            books_sel: selector(r#"div.z-list.zhover.zpointer a.stitle"#)?,
+            link_sel: selector("center a")?,
 
-            title_sel: Selector::parse(r#"#profile_top b.xcontrast_txt"#).unwrap(),
-            next_sel: Selector::parse(r#"span button.btn"#).unwrap(),
-            chapter_sel: Selector::parse(r#"#storytext"#).unwrap(),
+            title_sel: selector(r#"#profile_top b.xcontrast_txt"#)?,
+            author_sel: selector(r#"#profile_top a.xcontrast_txt"#)?,
+            next_sel: selector(r#"span button.btn"#)?,
+            chapter_sel: selector(r#"#storytext"#)?,
 
-        };
+        });
     }
 
     // Get all book urls in a genre, return next url to crawl
-    fn crawl_genre(&self, url: &String, book_urls: &mut Vec<String>) -> Option<String> {
-        let mut result = self.client.get(url).unwrap();
-        if !result.status().is_success() {
-            eprintln!("request to {} resulted in {}", url, result.status());
-            return None;
-        }
-
-        let text = result.text().unwrap();
+    fn crawl_genre(&self, url: &String, book_urls: &mut Vec<String>) -> Result<Option<String>, Error> {
+        let text = fetch(&self.client, url)?;
         let doc = Html::parse_document(&text);
-        let content = doc.select(&self.content_sel).next().unwrap();
+        let content = doc.select(&self.content_sel).next().ok_or_else(|| unexpected(url))?;
 
         // descending selectors for books
-        let base = base_url(&url).unwrap();
+        let base = base_url(&url)?;
 
-        for book in content.select(&self.books_sel) {
-            let book_url = book.value().attr("href").unwrap().to_string();
-            book_urls.push(base.join(&book_url).unwrap().into_string());
+        for book_url in content.select(&self.books_sel).filter_map(|b| b.value().attr("href")) {
+            book_urls.push(base.join(book_url)?.into_string());
         }
 
         // descending selectors for getting next page url's
-        let link = content.select(&self.link_sel).into_iter().last()?
-            .value().attr("href").unwrap().to_string();
+        let link = match content.select(&self.link_sel).into_iter().last().and_then(|l| l.value().attr("href")) {
+            Some(l) => l.to_string(),
+            None => return Ok(None),
+        };
 
-        return Some(base.join(&link).unwrap().into_string());
+        return Ok(Some(base.join(&link)?.into_string()));
     }
 
-    fn crawl_chapter(&self, url: &String) -> Option<String> {
+    fn crawl_chapter(&self, url: &String) -> Result<Option<String>, Error> {
 //        println!("url={}", url);
 
-        let mut result = self.client.get(url).unwrap();
-        if !result.status().is_success() {
-            eprintln!("request to {} resulted in {}", url, result.status());
-            return None;
-        }
-        let doc = Html::parse_document(&result.text().unwrap());
+        let doc = Html::parse_document(&fetch(&self.client, url)?);
 
-        let content = doc.select(&self.content_sel).next().unwrap();
+        let content = doc.select(&self.content_sel).next().ok_or_else(|| unexpected(url))?;
         let title = match content.select(&self.title_sel).next() {
             Some(t) => t.inner_html(),
             None => url.replace("/", ""),
         };
-        let text = content.select(&self.chapter_sel).next()?
+        let text = content.select(&self.chapter_sel).next().ok_or_else(|| unexpected(url))?
             .text().into_iter()
             .fold(String::new(), |a, x| a + x);
 
+        // the profile link, other links in the header go to reviews and the like
+        let author = content.select(&self.author_sel)
+            .find(|a| a.value().attr("href").map(|h| h.starts_with("/u/")).unwrap_or(false))
+            .map(|a| a.text().collect::<String>().trim().to_string());
+
         // save
         let message = store::Chapter {
             title,
             text,
+            author,
+            url: url.to_string(),
         };
-        self.store.save(message);
+        self.store.save(message)?;
 
         // build next url
-        let next = match content.select(&self.next_sel).next() {
-            Some(n) => n.value().attr("onclick").unwrap()
+        let next = match content.select(&self.next_sel).next().and_then(|n| n.value().attr("onclick")) {
+            Some(n) => n
                 .replace("self.location=", "")
                 .replace("'", "")
                 .trim()
                 .to_string(),
             None => {
-                return None;
+                return Ok(None);
             }
         };
 
 //        println!("next={:?}", next);
-        let base = base_url(&url).unwrap();
-        return Some(base.join(&next).unwrap().into_string());
+        let base = base_url(&url)?;
+        return Ok(Some(base.join(&next)?.into_string()));
+    }
+}
+
+// the text of the page at /url/
+pub fn fetch(client: &HttpClient, url: &str) -> Result<String, Error> {
+    let mut result = client.get(url)?;
+    if !result.status().is_success() {
+        return Err(Error::Http(format!("request to {} resulted in {}", url, result.status())));
     }
+    return Ok(result.text()?);
+}
+
+pub fn selector(s: &str) -> Result<Selector, Error> {
+    return Selector::parse(s).map_err(|e| Error::Parse(format!("bad selector {}: {:?}", s, e)));
+}
+
+// for pages missing what a selector looks for, usually because the site has changed
+pub fn unexpected(url: &str) -> Error {
+    return Error::Parse(format!("{} isn't laid out as expected", url));
 }
 
 pub fn base_url(url: &str) -> Result<Url, ParseError> {
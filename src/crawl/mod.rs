@@ -1,20 +1,7 @@
-use clap::ArgMatches;
-
+pub mod crawler;
 mod pool;
 mod dailymail;
 mod fanfiction;
-mod store;
-
-pub fn crawl_cmd(args: &ArgMatches) -> () {
-    let url = args.value_of("seed").unwrap();
-    let path = args.value_of("path").unwrap();
-    let store = store::new(path);
+pub mod store;
 
-    if args.is_present("fanfiction") {
-        fanfiction::crawl(url, store);
-    } else if args.is_present("dailymail") {
-        dailymail::crawl(url, store);
-    } else {
-        panic!("you must choose one of [fanfiction|dailymail]");
-    }
-}
\ No newline at end of file
+pub use self::crawler::{Crawler, Site};
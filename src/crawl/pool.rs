@@ -4,13 +4,15 @@ use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
+use crate::error::Error;
+
 enum Message {
     Crawl(String),
     Terminate,
 }
 
 pub trait Processor {
-    fn crawl(&self, url: String);
+    fn crawl(&self, url: String) -> Result<(), Error>;
 }
 
 struct Worker {
@@ -24,7 +26,12 @@ impl Worker {
             loop {
                 if let Ok(ref mut q) = queue.lock() {
                     match q.pop_back() {
-                        Some(Message::Crawl(url)) => processor.crawl(url),
+                        // one odd page shouldn't take the thread down with it
+                        Some(Message::Crawl(url)) => {
+                            if let Err(e) = processor.crawl(url.clone()) {
+                                eprintln!("skipping {}: {}", url, e);
+                            }
+                        }
                         Some(Message::Terminate) => return,
 
                         //queue is drained
@@ -2,6 +2,11 @@ use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::Path;
 
+use crate::error::{self, Error};
+
+// hidden so walking the store for training doesn't pick it up
+pub const INDEX: &str = ".index";
+
 pub struct Store {
     path: String,
 }
@@ -16,10 +21,12 @@ pub fn new(path: &str) -> Store {
 pub struct Chapter {
     pub title: String,
     pub text: String,
+    pub author: Option<String>,
+    pub url: String,
 }
 
 impl Store {
-    pub fn save(&self, msg: Chapter) {
+    pub fn save(&self, msg: Chapter) -> Result<(), Error> {
         let filename = msg.title.trim()
             .replace("\n", "⏺")
             .replace(" ", "_")
@@ -32,25 +39,39 @@ impl Store {
 
 //        println!("path is: {:?}", p);
         if !p.is_file() {
-            File::create(&p).unwrap();
+            File::create(&p).map_err(error::io(&format!("failed to create file {}", filename)))?;
+            self.index(&filename, &msg);
         }
 
         let mut file = OpenOptions::new()
             .write(true).append(true)
             .open(&p)
-            .unwrap();
+            .map_err(error::io(&format!("failed to open file {}", filename)))?;
 
         let mut text = msg.text.trim().to_string();
         while text.starts_with("\n") {
             text = text[1..text.len()].to_string();
         }
 
-        match file.write_all(msg.text.trim().as_bytes()) {
-            Err(e) => eprintln!("failed to write file{}: {}", filename, e),
-            Ok(_) => return,
-        }
+        file.write_all(msg.text.trim().as_bytes()).map_err(error::io(&format!("failed to write file {}", filename)))?;
 
         let _ = std::writeln!(file, "\n");
         let _ = file.flush();
+        return Ok(());
+    }
+
+    // record who wrote each file and where it came from, one `file\tauthor\turl` line per file
+    fn index(&self, filename: &str, msg: &Chapter) -> () {
+        let clean = |s: &str| s.replace('\t', " ").replace('\n', " ");
+        let line = format!("{}\t{}\t{}\n", filename, clean(msg.author.as_ref().map(|a| a.as_str()).unwrap_or("")),
+                           clean(&msg.url));
+
+        let result = OpenOptions::new()
+            .create(true).append(true)
+            .open(Path::new(&self.path).join(INDEX))
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+        if let Err(e) = result {
+            eprintln!("failed to index {}: {}", filename, e);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+extern crate isahc;
+extern crate sled;
+extern crate url;
+
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong, worded to be read on the command line. Subcommands hand these up to
+// main, which prints them and exits non-zero.
+#[derive(Debug)]
+pub enum Error {
+    // reading or writing a file, and what was being done with it
+    Io(String, io::Error),
+    // the db underneath a model
+    Storage(String),
+    // fetching a page
+    Http(String),
+    // bad arguments, specs and pages that aren't laid out as expected
+    Parse(String),
+    // dbs and model files that can't be read back
+    Format(String),
+    // a model asked for something it can't do, e.g. generating backwards without reverse chains
+    Model(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::Io(what, e) => write!(f, "{}: {}", what, e),
+            Error::Storage(m) | Error::Http(m) | Error::Parse(m) | Error::Format(m) | Error::Model(m) => write!(f, "{}", m),
+        };
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sled::Error> for Error {
+    fn from(e: sled::Error) -> Error {
+        return Error::Storage(format!("db error: {}", e));
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Error {
+        return Error::Format(format!("couldnt decode stored data: {}", e));
+    }
+}
+
+impl From<isahc::Error> for Error {
+    fn from(e: isahc::Error) -> Error {
+        return Error::Http(format!("request failed: {}", e));
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Error {
+        return Error::Parse(format!("bad url: {}", e));
+    }
+}
+
+// for map_err on io results, /what/ says what was being done
+pub fn io(what: &str) -> impl FnOnce(io::Error) -> Error + '_ {
+    return move |e| Error::Io(what.to_string(), e);
+}
@@ -0,0 +1,300 @@
+use std::collections::{HashMap, VecDeque};
+use rand::seq::SliceRandom;
+
+use crate::error::Error;
+use crate::generate::{anchored, choose, invent, load, render, sample, seed_stack, Loaded, Source, Switch, Unknown,
+                      MAX_RUN_ON, MAX_SENTENCE, PARAGRAPH_SENTENCES};
+use crate::model::{self, Model};
+use crate::model::smoothing::Smoothing;
+use crate::train::analyse::{self, create_corpus, DOCUMENT_END, SENTENCE_END, SENTENCE_START};
+use crate::train::bpe::{self, Bpe};
+use crate::train::vocab;
+
+// Text from one or more trained models, blended by weight. See the setters for the ways generation
+// can be steered.
+pub struct Generator<'m> {
+    models: Vec<(&'m Model, f64)>,
+    switch: Switch,
+    length: usize,
+    // phrase to grow the text from, and whether it ends the text or sits in the middle
+    anchor: Option<(String, bool)>,
+    unk_model: Option<&'m Model>,
+}
+
+// the text, and what each model brought to it for the generate command to show
+pub struct Generated {
+    pub text: String,
+    // (path, contexts, largest n, smoothing) of each model, in the order they were added
+    pub models: Vec<(String, usize, usize, Smoothing)>,
+}
+
+pub fn new<'m>() -> Generator<'m> {
+    return Generator {
+        models: Vec::new(),
+        switch: Switch::Word,
+        length: 10,
+        anchor: None,
+        unk_model: None,
+    };
+}
+
+impl<'m> Generator<'m> {
+    // blend in another model, weights are relative to the other models'
+    pub fn model(mut self, model: &'m Model, weight: f64) -> Generator<'m> {
+        self.models.push((model, weight));
+        return self;
+    }
+
+    pub fn switch(mut self, switch: Switch) -> Generator<'m> {
+        self.switch = switch;
+        return self;
+    }
+
+    // how many words to generate
+    pub fn length(mut self, length: usize) -> Generator<'m> {
+        self.length = length;
+        return self;
+    }
+
+    // finish on this phrase, the models need reverse chains
+    pub fn end_with(mut self, phrase: &str) -> Generator<'m> {
+        self.anchor = Some((phrase.to_string(), true));
+        return self;
+    }
+
+    // grow the text outwards from this word or phrase, the models need reverse chains
+    pub fn keyword(mut self, phrase: &str) -> Generator<'m> {
+        self.anchor = Some((phrase.to_string(), false));
+        return self;
+    }
+
+    // character model to make up words with where the models say <unk>
+    pub fn unk_model(mut self, model: &'m Model) -> Generator<'m> {
+        self.unk_model = Some(model);
+        return self;
+    }
+
+    pub fn generate(&self) -> Result<Generated, Error> {
+        if self.models.is_empty() {
+            return Err(Error::Model("nothing to generate with, add a model".to_string()));
+        }
+        let count = self.length;
+        let switch = self.switch;
+
+        // a zero weight would leave its model out, and all zero can't be normalised
+        if let Some((_, w)) = self.models.iter().find(|(_, w)| !(*w > 0.0 && w.is_finite())) {
+            return Err(Error::Parse(format!("bad weight {}, weights must be above 0", w)));
+        }
+        let total: f64 = self.models.iter().map(|(_, w)| w).sum();
+        let weights: Vec<f64> = self.models.iter().map(|(_, w)| w / total).collect();
+
+        // a phrase to finish on, or a keyword to grow the text around. both are worked out backwards
+        // with the reverse chains.
+        let anchor: Option<(Vec<String>, bool)> = self.anchor.as_ref().map(|(p, end)| (create_corpus(p), *end));
+
+        let mut loaded: Vec<Loaded> = Vec::new();
+        let mut chars: Option<bool> = None;
+        let mut pieces: Option<Option<Bpe>> = None;
+        let mut rare: HashMap<String, Vec<String>> = HashMap::new();
+        for (m, _) in self.models.iter() {
+            let meta = m.meta();
+
+            // character and word models can't be blended, nor can models with different tokenizers
+            let c = model::is_char_model(meta)?;
+            if chars.map(|other| other != c).unwrap_or(false) {
+                return Err(Error::Model(format!("can't mix character and word models, {} is a {} model", m.path(),
+                                   if c { "character" } else { "word" })));
+            }
+            chars = Some(c);
+
+            let b = model::load_bpe(meta)?;
+            if let Some(other) = pieces.as_ref() {
+                if other.as_ref().map(|o| &o.merges) != b.as_ref().map(|b| &b.merges) {
+                    return Err(Error::Model(format!("can't mix models with different tokenizers, {} doesn't match {}",
+                                                    m.path(), self.models[0].0.path())));
+                }
+            }
+            pieces = Some(b);
+
+            model::load_rare(meta)?.into_iter()
+                .for_each(|(class, words)| rare.entry(class).or_insert_with(Vec::new).extend(words));
+            if c && anchor.is_some() {
+                return Err(Error::Model(format!("--end-with and --keyword need word models, {} is a character model",
+                                                m.path())));
+            }
+
+            loaded.push(load(m, anchor.is_some())?);
+        }
+
+        // subword models generate pieces of words, anchors have to be split up the same way
+        let bpe: Option<Bpe> = pieces.unwrap_or(None);
+        let anchor = match (anchor, bpe.as_ref()) {
+            (Some((words, end)), Some(b)) => Some((b.encode_words(&words), end)),
+            (a, _) => a,
+        };
+
+        let models: Vec<Box<dyn Source + '_>> = loaded.iter().map(|l| l.forward()).collect();
+        let used: Vec<(String, usize, usize, Smoothing)> = self.models.iter().zip(models.iter())
+            .map(|((m, _), s)| (m.path().to_string(), s.contexts(), s.order() - 1, m.smoothed_with()))
+            .collect();
+        let largest_n = models.iter().map(|m| m.order() - 1).max().unwrap();
+
+        // a character model to make up words where the model says <unk>
+        let unk_model: Option<Loaded> = match self.unk_model {
+            Some(m) => {
+                if !model::is_char_model(m.meta())? {
+                    return Err(Error::Model(format!("--unk-model {} isn't a character model, train it with --unit char",
+                                                    m.path())));
+                }
+                Some(load(m, false)?)
+            }
+            None => None,
+        };
+        let inventor: Option<(Vec<Box<dyn Source + '_>>, usize)> = unk_model.as_ref()
+            .map(|l| l.forward())
+            .map(|m| {
+                let n = m.order() - 1;
+                (vec![m], n)
+            });
+        let unknown = Unknown {
+            rare: &rare,
+            inventor: inventor.as_ref().map(|(m, n)| (m, *n)),
+        };
+
+        let mut rng = rand::thread_rng();
+
+        if chars == Some(true) {
+            let text = invent(&models, &weights, switch, count, largest_n, &mut rng).join(" ");
+            return Ok(Generated { text, models: used });
+        }
+
+        if let Some((words, end)) = anchor {
+            let backward: Vec<Box<dyn Source + '_>> = loaded.iter().map(|l| l.backward().unwrap()).collect();
+
+            // blending per sentence or paragraph makes no difference to a single passage, so pick once
+            let i = (0..models.len()).collect::<Vec<usize>>()
+                .choose_weighted(&mut rng, |&i| weights[i]).cloned().unwrap();
+            let (forward, backward): (Vec<(&dyn Source, f64)>, Vec<(&dyn Source, f64)>) = match switch {
+                Switch::Word => (
+                    models.iter().map(|m| &**m).zip(weights.iter().cloned()).collect(),
+                    backward.iter().map(|m| &**m).zip(weights.iter().cloned()).collect(),
+                ),
+                _ => (vec![(&*models[i], 1.0)], vec![(&*backward[i], 1.0)]),
+            };
+
+            let tokens: Vec<String> = anchored(&forward, &backward, words, end, count, largest_n, &mut rng).into_iter()
+                .map(|t| if vocab::is_unknown(&t) { unknown.word(&t, &mut rng) } else { t })
+                .collect();
+            return Ok(Generated { text: render(&tokens, bpe.is_some()), models: used });
+        }
+
+        // models trained with sentence markers start and end sentences and documents by themselves
+        let sentences_known = models.iter().all(|m| m.contains(SENTENCE_END));
+        let max_sentence = if sentences_known { MAX_RUN_ON } else { MAX_SENTENCE };
+
+        let mut sentence = String::new();
+
+        // the model(s) words are currently drawn from
+        let mut active: Vec<(&dyn Source, f64)> = choose(&models, &weights, switch, &mut rng);
+
+        // the last few words generated, as deep as the longest context. the next word is sampled from
+        // the model given this history.
+        let mut stack: VecDeque<String> = VecDeque::with_capacity(largest_n + 1);
+        seed_stack(&active, sentences_known, &mut rng, &mut stack)?;
+
+        for w in stack.iter().filter(|w| !analyse::is_sentinel(w)) {
+            sentence.push_str(w);
+            sentence.push_str(" ");
+        }
+
+        // now run the main loop until we hit our target length.
+        let mut len = sentence.split_whitespace().count();
+        let mut words = len;
+        let mut sentences = 0;
+
+        while len < count {
+            let history: Vec<String> = stack.iter().cloned().collect();
+            let next = if words < max_sentence {
+                sample(&active, &history, &mut rng)
+            } else {
+                None
+            };
+
+            // the end of a document ends its last sentence as well
+            let ended = match next.as_ref().map(|w| w.as_str()) {
+                Some(SENTENCE_START) => false,
+                Some(SENTENCE_END) | Some(DOCUMENT_END) | None => true,
+                // only the last piece of a word finishes it
+                Some(w) if vocab::is_unknown(w) => {
+                    sentence.push_str(&unknown.word(w, &mut rng));
+                    sentence.push_str(" ");
+                    len += 1;
+                    words += 1;
+                    false
+                }
+                Some(w) if bpe.is_some() && !w.ends_with(bpe::WORD_END) => {
+                    sentence.push_str(w);
+                    false
+                }
+                Some(w) => {
+                    sentence.push_str(w.trim_end_matches(bpe::WORD_END));
+                    sentence.push_str(" ");
+                    len += 1;
+                    words += 1;
+                    false
+                }
+            };
+
+            if !ended {
+                stack.push_back(next.unwrap());
+                if stack.len() > largest_n {
+                    stack.pop_front();
+                }
+                continue;
+            }
+
+            // nothing to end if the sentence never got going
+            let empty = words == 0;
+            if !empty {
+                if sentence.ends_with(' ') {
+                    sentence.pop();
+                }
+                sentence.push_str(". ");
+                sentences += 1;
+            }
+            words = 0;
+
+            let document = next.as_ref().map(|w| w == DOCUMENT_END).unwrap_or(false);
+            let paragraph = (document && !sentence.ends_with('\n')) || (!empty && sentences % PARAGRAPH_SENTENCES == 0);
+            if paragraph {
+                sentence.push_str("\n\n");
+            }
+            if switch == Switch::Sentence || (switch == Switch::Paragraph && paragraph) {
+                active = choose(&models, &weights, switch, &mut rng);
+            }
+
+            match next {
+                // carry on from the end of the sentence, the model knows what follows one
+                Some(w) if !document && sentences_known && switch == Switch::Word => {
+                    stack.push_back(w);
+                    if stack.len() > largest_n {
+                        stack.pop_front();
+                    }
+                }
+                // dead end, run-on, a new document or a different model: start a new sentence afresh
+                _ => {
+                    stack.clear();
+                    seed_stack(&active, sentences_known, &mut rng, &mut stack)?;
+                    for w in stack.iter().filter(|w| !analyse::is_sentinel(w)) {
+                        sentence.push_str(w);
+                        sentence.push_str(" ");
+                        len += 1;
+                        words += 1;
+                    }
+                }
+            }
+        }
+
+        return Ok(Generated { text: sentence, models: used });
+    }
+}
@@ -1,126 +1,398 @@
-extern crate sled;
 extern crate rand;
 
-use clap::ArgMatches;
 use std::collections::{HashMap, HashSet, VecDeque};
-use sled::{IVec, Tree};
-use self::rand::seq::IteratorRandom;
-use std::convert::TryInto;
+use self::rand::Rng;
+use self::rand::seq::{IteratorRandom, SliceRandom};
 use self::rand::prelude::ThreadRng;
 
-pub fn run_cmd(args: &ArgMatches) -> () {
-    let db_path = match args.value_of("dbpath") {
-        Some(v) => v,
-        None => "test",
-    };
-    let count = match args.value_of("length") {
-        Some(v) => v.parse::<usize>().unwrap(),
-        None => 10,
-    };
+use crate::error::Error;
+use crate::model::{self, Counts, Kind, Model};
+use crate::train::analyse::{self, DOCUMENT_END, SENTENCE_END, SENTENCE_START};
+use crate::train::bpe;
+use crate::train::vocab;
+use crate::model::backoff::UNKNOWN;
+use crate::model::compiled::{Compiled, Table};
+use crate::model::smoothing::{self, Estimator, Smoothing};
 
-    let db = sled::Db::open(db_path).unwrap();
-    let groups = db.open_tree("groups").unwrap();
-
-    // create m * n-grams
-    let chain_set: HashSet<u32> = groups.into_iter().fold(HashSet::new(), |mut s, v| {
-        let (_, g) = v.unwrap();
-        s.insert(ivec_to_u32(g));
-        return s;
-    });
-    let mut largest_n: u32 = 0;
-
-    let mut chains: HashMap<u32, Tree> = HashMap::new();
-    chain_set.iter().for_each(|&g| {
-        chains.insert(g, db.open_tree(u32_to_ivec(g)).unwrap());
-        if g > largest_n {
-            largest_n = g
-        }
-    });
+pub mod generator;
 
-    println!("chains: {}\n largest n: {}", chains.len(), largest_n);
+pub use self::generator::Generator;
 
-    let mut rng = rand::thread_rng();
+// What generation asks of a model. Dbs are loaded into counts and smoothed as they're read, compiled
+// models are queried where they lie in the mapped file.
+pub trait Source {
+    fn prob(&self, context: &[String], word: &str) -> f64;
+    // words seen right after the context in training, cut down to what the model can use of it
+    fn followers(&self, context: &[String]) -> Vec<&str>;
+    fn contains(&self, word: &str) -> bool;
+    // highest n-gram order, i.e. longest context + 1
+    fn order(&self) -> usize;
+    fn contexts(&self) -> usize;
+    // a random context seen in training, to start from when the model knows nothing of sentences
+    fn start(&self, rng: &mut ThreadRng) -> Option<Vec<String>>;
+}
 
-    let mut sentence = String::new();
+impl<'a> Source for Box<dyn Estimator + 'a> {
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        return (**self).prob(context, word);
+    }
 
-    // use a circular buffer as our 'stack' of last seen words. we will then try to find a bunch of
-    // matches across all chains (stack should be as deep as the largest n-gram size), and select
-    // a random one from those that provide an answer.
-    let mut stack: VecDeque<String> = VecDeque::with_capacity(largest_n as usize);
-    seed_stack(&chains, &mut rng, &mut stack);
+    fn followers(&self, context: &[String]) -> Vec<&str> {
+        let counts = self.counts();
+        return match counts.successors(counts.history(context)) {
+            Some(s) => s.keys().map(|w| w.as_str()).collect(),
+            None => Vec::new(),
+        };
+    }
 
-    println!("initial stack: {:?}", stack);
+    fn contains(&self, word: &str) -> bool {
+        return self.counts().contains(word);
+    }
 
-    // now run the main loop until we hit our target length.
-    let mut len = 0;
+    fn order(&self) -> usize {
+        return self.counts().order;
+    }
+
+    fn contexts(&self) -> usize {
+        return self.counts().contexts.len();
+    }
+
+    fn start(&self, rng: &mut ThreadRng) -> Option<Vec<String>> {
+        return self.counts().contexts.keys().filter(|k| !k.is_empty()).choose(rng).cloned();
+    }
+}
 
-    while len < count {
-        if stack.len() > largest_n as usize {
-            for _ in 0..stack.len() - largest_n as usize {
-                sentence.push_str(&stack.pop_back().unwrap());
-                sentence.push_str(" ");
-                len += 1
+impl<'a> Source for Table<'a> {
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        return Table::prob(self, context, word);
+    }
+
+    fn followers(&self, context: &[String]) -> Vec<&str> {
+        return Table::followers(self, context);
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        return Table::contains(self, word);
+    }
+
+    fn order(&self) -> usize {
+        return self.order;
+    }
+
+    fn contexts(&self) -> usize {
+        return self.contexts;
+    }
+
+    fn start(&self, rng: &mut ThreadRng) -> Option<Vec<String>> {
+        // contexts are sorted, the empty one comes first if it's there
+        let first = if self.contexts > 0 && self.words(0).is_empty() { 1 } else { 0 };
+        return (first..self.contexts).choose(rng).map(|i| self.words(i));
+    }
+}
+
+// a db's chains read into counts, or a compiled model
+enum Loaded<'m> {
+    Db(Counts, Option<Counts>, Smoothing),
+    Compiled(&'m Compiled),
+}
+
+// Get /model/ ready to generate with. Reverse chains are only read from a db when /reverse/ asks for
+// them.
+fn load(model: &Model, reverse: bool) -> Result<Loaded<'_>, Error> {
+    return match model.kind() {
+        Kind::Compiled(c) => {
+            if reverse && c.reverse().is_none() {
+                return Err(Error::Model(format!("{} has no reverse chains, compile it from a db trained with --reverse", model.path())));
+            }
+            Ok(Loaded::Compiled(c))
+        }
+        Kind::Db(db) => {
+            let backward = if reverse {
+                match model::load_reverse(db)? {
+                    Some(c) => Some(c),
+                    None => return Err(Error::Model(format!("db {} has no reverse chains, train it with --reverse", model.path()))),
+                }
+            } else {
+                None
+            };
+            Ok(Loaded::Db(model::load(db)?, backward, model.smoothed_with()))
+        }
+    };
+}
+
+impl<'m> Loaded<'m> {
+    fn forward(&self) -> Box<dyn Source + '_> {
+        return match self {
+            Loaded::Db(c, _, s) => Box::new(smoothing::build(c, *s)),
+            Loaded::Compiled(c) => Box::new(c.forward()),
+        };
+    }
+
+    fn backward(&self) -> Option<Box<dyn Source + '_>> {
+        return match self {
+            Loaded::Db(_, r, s) => r.as_ref().map(|c| Box::new(smoothing::build(c, *s)) as Box<dyn Source + '_>),
+            Loaded::Compiled(c) => c.reverse().map(|t| Box::new(t) as Box<dyn Source + '_>),
+        };
+    }
+}
+
+// sentences are cut here by models that can't end them themselves
+const MAX_SENTENCE: usize = 25;
+// models trained with sentence markers end their own sentences, this only stops the odd run-on
+const MAX_RUN_ON: usize = 100;
+const PARAGRAPH_SENTENCES: usize = 5;
+// made up words longer than this are given up on
+const MAX_WORD: usize = 30;
+
+// how often generation picks which of several models to use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Switch {
+    // blend all models for every word
+    Word,
+    Sentence,
+    Paragraph,
+}
+
+// what to put where the model generates <unk>
+struct Unknown<'a> {
+    // the rare words each <unk> class replaced in training
+    rare: &'a HashMap<String, Vec<String>>,
+    // a character model and its context length, to make words up with instead
+    inventor: Option<(&'a Vec<Box<dyn Source + 'a>>, usize)>,
+}
+
+impl<'a> Unknown<'a> {
+    // A word of the right class made up by the character model, failing that one of the rare words of
+    // that class, failing that any rare word at all. The class is left as is if there's nothing else.
+    fn word(&self, class: &str, rng: &mut ThreadRng) -> String {
+        if let Some((models, n)) = self.inventor {
+            let classes = class != UNKNOWN;
+            for w in invent(models, &vec![1.0], Switch::Word, 10, n, rng) {
+                if !classes || vocab::class(&w, true) == class {
+                    return w;
+                }
             }
-        } else if stack.len() <= 0 {
-            sentence.remove(sentence.len()-1);
-            sentence.push_str(". ");
-            seed_stack(&chains, &mut rng, &mut stack);
         }
 
-        let k = stack.len() as u32;
-        match chains.get(&k) {
-            Some(t) => {
-                match t.get(bincode::serialize(&stack).unwrap()).unwrap() {
-                    Some(vec) => {
-                        let words: HashSet<String> = bincode::deserialize(&vec).unwrap();
-                        words.iter().for_each(|w| stack.push_front(w.to_string()));
+        if let Some(w) = self.rare.get(class).and_then(|words| words.choose(rng)) {
+            return w.to_string();
+        }
+        return match self.rare.values().flat_map(|v| v.iter()).choose(rng) {
+            Some(w) => w.to_string(),
+            None => class.to_string(),
+        };
+    }
+}
+
+// Make up /count/ words with a character model, one character at a time from the start of a word to
+// its end. A model is picked per word unless blending per character.
+fn invent<'a>(models: &'a Vec<Box<dyn Source + 'a>>, weights: &Vec<f64>, switch: Switch, count: usize,
+              largest_n: usize, rng: &mut ThreadRng) -> Vec<String> {
+    let mut result: Vec<String> = Vec::with_capacity(count);
+
+    // a model that never finishes a word shouldn't keep us here forever
+    let mut attempts = 0;
+    while result.len() < count && attempts < count * 10 {
+        attempts += 1;
+        let active = choose(models, weights, switch, rng);
+
+        let mut stack: VecDeque<String> = VecDeque::with_capacity(largest_n + 1);
+        stack.push_back(SENTENCE_START.to_string());
+        let mut word = String::new();
+
+        let finished = loop {
+            if word.chars().count() > MAX_WORD {
+                break false;
+            }
+
+            let history: Vec<String> = stack.iter().cloned().collect();
+            match sample(&active, &history, rng) {
+                Some(ref c) if c == SENTENCE_END || c == DOCUMENT_END => break true,
+                Some(c) => {
+                    if c != SENTENCE_START {
+                        word.push_str(&c);
                     }
-                    None => {
-                        sentence.push_str(&stack.pop_back().unwrap());
-                        sentence.push_str(" ");
-                        len += 1
+                    stack.push_back(c);
+                    if stack.len() > largest_n {
+                        stack.pop_front();
                     }
                 }
+                None => break false,
             }
-            None => {
-                // todo reduce key and try again. for now just bail
-                sentence.push_str(&stack.pop_back().unwrap());
-                sentence.push_str(" ");
-                len += 1;
-                println!("no chain found for key {} stack is now {}", k, stack.len())
+        };
+
+        if finished && !word.is_empty() {
+            result.push(word);
+        }
+    }
+
+    return result;
+}
+
+// join generated words into text, sentence markers become full stops and document ends paragraphs.
+// subword /pieces/ are joined back up into words first.
+fn render(words: &[String], pieces: bool) -> String {
+    let joined: Vec<String>;
+    let words = if pieces {
+        joined = join_pieces(words);
+        &joined
+    } else {
+        words
+    };
+
+    let mut text = String::new();
+    for w in words {
+        match w.as_str() {
+            SENTENCE_START => continue,
+            SENTENCE_END | DOCUMENT_END if text.trim().is_empty() => continue,
+            SENTENCE_END | DOCUMENT_END => {
+                if text.ends_with(' ') {
+                    text.pop();
+                }
+                if !text.ends_with('.') && !text.ends_with('\n') {
+                    text.push('.');
+                }
+                text.push_str(if w == DOCUMENT_END { "\n\n" } else { " " });
             }
+            _ => {
+                text.push_str(w);
+                text.push(' ');
+            }
+        }
+    }
+
+    return text.trim_end().to_string();
+}
+
+// rejoin word pieces, leaving sentence markers where they are
+fn join_pieces(tokens: &[String]) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut run: Vec<String> = Vec::new();
+    for t in tokens {
+        if analyse::is_sentinel(t) {
+            result.extend(bpe::detokenize(&run));
+            run.clear();
+            result.push(t.to_string());
+        } else {
+            run.push(t.to_string());
         }
     }
+    result.extend(bpe::detokenize(&run));
 
-    println!("finishing run, stack is: {:?}, len: {}", stack, len);
-    println!("output:\n\n{}", sentence);
+    return result;
 }
 
+// Grow /count/ words of text around the anchor words: all of it backwards from the anchor when it's
+// an ending, otherwise half backwards and half forwards from it.
+fn anchored(forward: &[(&dyn Source, f64)], backward: &[(&dyn Source, f64)], anchor: Vec<String>,
+            end: bool, count: usize, largest_n: usize, rng: &mut ThreadRng) -> Vec<String> {
+    let remaining = if count > anchor.len() { count - anchor.len() } else { 0 };
+    let before = if end { remaining } else { remaining / 2 };
+
+    let reversed: Vec<String> = anchor.iter().rev().cloned().collect();
+    let mut output: Vec<String> = walk(backward, &reversed, before, largest_n, rng);
+    output.reverse();
+    output.extend(anchor.iter().cloned());
+
+    if !end {
+        output.extend(walk(forward, &anchor, remaining - before, largest_n, rng));
+    }
 
-fn seed_stack(chains: &HashMap<u32, Tree>, rng: &mut ThreadRng, stack: &mut VecDeque<String>) {
-    // choose a random chain
-    let start = match chains.iter().choose(rng) {
-        Some((_, v)) => v,
-        None => {
-            panic!("couldnt choose a random starting chain")
+    return output;
+}
+
+// sample up to /n/ words following on from /seed/, stops early if the models run dry
+fn walk(models: &[(&dyn Source, f64)], seed: &[String], n: usize, largest_n: usize,
+        rng: &mut ThreadRng) -> Vec<String> {
+    let mut stack: VecDeque<String> = seed.iter().cloned().collect();
+    while stack.len() > largest_n {
+        stack.pop_front();
+    }
+
+    let mut words: Vec<String> = Vec::with_capacity(n);
+    while words.len() < n {
+        let history: Vec<String> = stack.iter().cloned().collect();
+        match sample(models, &history, rng) {
+            Some(w) => {
+                stack.push_back(w.to_string());
+                if stack.len() > largest_n {
+                    stack.pop_front();
+                }
+                words.push(w);
+            }
+            None => break,
         }
-    };
+    }
 
-    // choose a random kv pair from said chain
-    let (k, v) = start.iter().choose(rng).unwrap().unwrap();
+    return words;
+}
 
-    let x: Vec<String> = bincode::deserialize(&k).unwrap();
-    x.into_iter().for_each(|w| stack.push_front(w));
+// every model with its weight when blending per word, otherwise a single model picked by weight
+fn choose<'a>(models: &'a Vec<Box<dyn Source + 'a>>, weights: &Vec<f64>, switch: Switch,
+              rng: &mut ThreadRng) -> Vec<(&'a dyn Source, f64)> {
+    if switch == Switch::Word {
+        return models.iter().map(|m| &**m).zip(weights.iter().cloned()).collect();
+    }
 
-    let y: HashSet<String> = bincode::deserialize(&v).unwrap();
-    y.into_iter().for_each(|w| stack.push_front(w));
+    let mut r = rng.gen::<f64>();
+    for (m, w) in models.iter().zip(weights.iter()) {
+        if r < *w {
+            return vec![(&**m, 1.0)];
+        }
+        r -= w;
+    }
+    return vec![(&**models.last().unwrap(), 1.0)];
 }
 
-fn u32_to_ivec(x: u32) -> IVec {
-    IVec::from(x.to_be_bytes().to_vec())
+// Sample the next word from the weighted mixture of models. Candidates come from the longest context
+// seen in training by any of the models and are weighted by their blended smoothed probability;
+// whatever probability is left for unseen words is used to back off to the candidates of a shorter
+// context instead.
+pub fn sample<R: Rng>(models: &[(&dyn Source, f64)], history: &[String], rng: &mut R) -> Option<String> {
+    let mut context = history;
+    loop {
+        let candidates: HashSet<&str> = models.iter()
+            .flat_map(|(m, _)| m.followers(context))
+            .collect();
+
+        if !candidates.is_empty() {
+            let weights: Vec<(&str, f64)> = candidates.into_iter()
+                .map(|w| (w, models.iter().map(|(m, l)| l * m.prob(history, w)).sum()))
+                .collect();
+            let mass: f64 = weights.iter().map(|(_, p)| p).sum();
+
+            if mass > 0.0 && (context.is_empty() || rng.gen::<f64>() < mass) {
+                let mut r = rng.gen::<f64>() * mass;
+                for (w, p) in weights.iter() {
+                    if r < *p {
+                        return Some(w.to_string());
+                    }
+                    r -= p;
+                }
+                return weights.last().map(|(w, _)| w.to_string());
+            }
+        }
+
+        if context.is_empty() {
+            return None;
+        }
+        context = &context[1..];
+    }
 }
 
-fn ivec_to_u32(x: IVec) -> u32 {
-    let a: [u8; 4] = x.to_vec().as_slice().try_into().unwrap();
-    return u32::from_be_bytes(a);
+fn seed_stack(models: &[(&dyn Source, f64)], sentences: bool, rng: &mut ThreadRng,
+              stack: &mut VecDeque<String>) -> Result<(), Error> {
+    // the start of a sentence, when the models know where those are
+    if sentences {
+        stack.push_back(SENTENCE_START.to_string());
+        return Ok(());
+    }
+
+    // choose a random context one of the models was trained on
+    let start = models.choose_weighted(rng, |(_, w)| *w).ok()
+        .and_then(|(model, _)| model.start(rng))
+        .ok_or_else(|| Error::Model("couldnt choose a random starting context, is the model empty?".to_string()))?;
+
+    start.iter().for_each(|w| stack.push_back(w.to_string()));
+    return Ok(());
 }
@@ -0,0 +1,17 @@
+// The markov engine behind the rustygenmo command line, for use from other tools: crawl a corpus
+// with a Crawler, train a db on it with a Trainer, open it as a Model to score, export, compile or
+// prune, and make text from one or more with a Generator.
+#[macro_use]
+extern crate partial_application;
+
+pub mod crawl;
+pub mod error;
+pub mod generate;
+pub mod model;
+pub mod train;
+
+pub use crate::crawl::{crawler, Crawler, Site};
+pub use crate::error::Error;
+pub use crate::generate::{generator, Generator, Switch};
+pub use crate::model::Model;
+pub use crate::train::{trainer, Persistent, Trainer};
@@ -1,14 +1,9 @@
 extern crate clap;
 
-#[macro_use]
-extern crate partial_application;
-
-mod train;
-mod generate;
-mod crawl;
-
 use clap::{App, Arg};
 
+mod cmd;
+
 fn main() {
     let matches = App::new("rustygenmo")
         .version(clap::crate_version!())
@@ -20,8 +15,23 @@ fn main() {
             .about("corpus analysis")
             .arg(Arg::with_name("file")
                 .short("f")
-                .help("Path to a file to analyse")
-                .required(true)
+                .help("File, directory or glob to analyse, repeat for several sources")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("by-source")
+                .long("by-source")
+                .help("break results down per -f source instead of aggregating them")
+                .takes_value(false))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("output format (default text)")
+                .possible_values(&["text", "json", "csv", "tsv"])
+                .takes_value(true))
+            .arg(Arg::with_name("plot")
+                .long("plot")
+                .help("draw the results instead of listing them: `term` for the terminal or a path to write an svg to")
                 .takes_value(true))
             .arg(Arg::with_name("first")
                 .short("a")
@@ -32,11 +42,108 @@ fn main() {
                 .help("return last n items only")
                 .takes_value(true))
             .subcommand(App::new("dump")
-                .about("Dump all unique words considered when parsing corpus"))
+                .about("Dump all unique words considered when parsing corpus, with their counts"))
             .subcommand(App::new("words")
                 .about("Word frequency"))
             .subcommand(App::new("groups")
-                .about("Word frequency groups")))
+                .about("Word frequency groups"))
+            .subcommand(App::new("ngrams")
+                .about("N-gram frequency")
+                .arg(Arg::with_name("n")
+                    .short("n")
+                    .help("n-gram lengths to count, e.g. 2..5 or 3 (default 2)")
+                    .takes_value(true)))
+            .subcommand(App::new("collocations")
+                .about("Word pairs ranked by PMI, log-likelihood ratio and t-score")
+                .arg(Arg::with_name("min-count")
+                    .short("m")
+                    .long("min-count")
+                    .help("ignore pairs seen fewer times than this (default 5)")
+                    .takes_value(true))
+                .arg(Arg::with_name("window")
+                    .short("w")
+                    .long("window")
+                    .help("how many following words pair up with each word (default 1)")
+                    .takes_value(true))
+                .arg(Arg::with_name("rank")
+                    .short("r")
+                    .long("rank")
+                    .help("score to sort by (default llr)")
+                    .possible_values(&["pmi", "llr", "t"])
+                    .takes_value(true)))
+            .subcommand(App::new("stats")
+                .about("Lexical statistics: types, hapaxes, TTR/MATTR, Yule's K, Zipf and Heaps fits")
+                .arg(Arg::with_name("window")
+                    .short("w")
+                    .long("window")
+                    .help("window size for the moving average type-token ratio (default 500)")
+                    .takes_value(true))
+                .arg(Arg::with_name("curve")
+                    .long("curve")
+                    .help("print the vocabulary growth curve (tokens, types) instead of the summary")
+                    .takes_value(false)))
+            .subcommand(App::new("concordance")
+                .about("Keyword in context lines for a word or phrase")
+                .arg(Arg::with_name("phrase")
+                    .help("word or phrase to look for")
+                    .required(true)
+                    .index(1))
+                .arg(Arg::with_name("width")
+                    .short("w")
+                    .long("width")
+                    .help("characters of context either side (default 40)")
+                    .takes_value(true)))
+            .subcommand(App::new("compare")
+                .about("Keyness: words and n-grams over-represented in one corpus compared to another")
+                .arg(Arg::with_name("a")
+                    .help("first corpus, a file, directory or glob")
+                    .required(true)
+                    .index(1))
+                .arg(Arg::with_name("b")
+                    .help("second corpus, a file, directory or glob")
+                    .required(true)
+                    .index(2))
+                .arg(Arg::with_name("n")
+                    .short("n")
+                    .help("n-gram lengths to compare, e.g. 1..3 (default 1, words)")
+                    .takes_value(true))
+                .arg(Arg::with_name("min-count")
+                    .short("m")
+                    .long("min-count")
+                    .help("ignore items seen fewer times than this in both corpora (default 5)")
+                    .takes_value(true))
+                .arg(Arg::with_name("min-ll")
+                    .long("min-ll")
+                    .help("smallest log-likelihood to report, 3.84 is p < 0.05 (default), 6.63 p < 0.01")
+                    .takes_value(true)))
+            .subcommand(App::new("style")
+                .about("Readability and style: sentence/word lengths, Flesch-Kincaid, fog, dialogue, punctuation")
+                .arg(Arg::with_name("distribution")
+                    .long("distribution")
+                    .help("print a full distribution instead of the summary")
+                    .possible_values(&["sentence", "word", "punctuation"])
+                    .takes_value(true)))
+            .subcommand(App::new("authors")
+                .about("Stylometry: Burrows' Delta and cosine distances between the authors in the crawler index")
+                .arg(Arg::with_name("unknown")
+                    .short("u")
+                    .long("unknown")
+                    .help("file, directory or glob of unattributed text to compare against every author")
+                    .takes_value(true))
+                .arg(Arg::with_name("features")
+                    .short("n")
+                    .long("features")
+                    .help("number of most frequent words to profile (default 150)")
+                    .takes_value(true))
+                .arg(Arg::with_name("min-words")
+                    .short("m")
+                    .long("min-words")
+                    .help("leave out authors with less text than this (default 1000)")
+                    .takes_value(true))
+                .arg(Arg::with_name("profiles")
+                    .long("profiles")
+                    .help("print each author's word frequencies and z-scores instead of distances")
+                    .takes_value(false))))
 
         // training
         .subcommand(App::new("train")
@@ -49,11 +156,199 @@ fn main() {
             .arg(Arg::with_name("dbpath")
                 .short("d")
                 .help("path to db")
-                .required(true)
+                .required_unless("memory")
                 .takes_value(true))
             .arg(Arg::with_name("count")
                 .short("c")
-                .help("number of groups to use")
+                .help("number of groups to use, same as --grouping top:<count>")
+                .conflicts_with("grouping")
+                .takes_value(true))
+            .arg(Arg::with_name("grouping")
+                .short("g")
+                .long("grouping")
+                .help("how words are grouped into n-gram orders: top[:count]|log[:base]|quantile|function|fixed[:n] (default log)")
+                .takes_value(true))
+            .arg(Arg::with_name("max-order")
+                .long("max-order")
+                .help("longest context any group gets (default 5)")
+                .takes_value(true))
+            .arg(Arg::with_name("unit")
+                .long("unit")
+                .help("train on words, or on characters to make up new words (default word)")
+                .possible_values(&["word", "char"])
+                .takes_value(true))
+            .arg(Arg::with_name("order")
+                .long("order")
+                .help("n-gram order of a --unit char model (default 4)")
+                .requires("unit")
+                .conflicts_with_all(&["grouping", "count", "max-order"])
+                .takes_value(true))
+            .arg(Arg::with_name("min-count")
+                .long("min-count")
+                .help("replace words seen fewer times than this with <unk>")
+                .takes_value(true))
+            .arg(Arg::with_name("max-vocab")
+                .long("max-vocab")
+                .help("keep only this many of the most frequent words, the rest become <unk>")
+                .takes_value(true))
+            .arg(Arg::with_name("unk-classes")
+                .long("unk-classes")
+                .help("replace rare words with a class of <unk> by word shape, e.g. <unk-ing>")
+                .takes_value(false))
+            .arg(Arg::with_name("group-scope")
+                .long("group-scope")
+                .help("count frequencies over the whole corpus, or tf-idf weighted per document or per source")
+                .possible_values(&["corpus", "document", "source"])
+                .takes_value(true))
+            .arg(Arg::with_name("holdout")
+                .long("holdout")
+                .help("fraction of corpus files to keep out of training for evaluate, e.g. 0.1")
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("smoothing to use with this model: backoff[:discount]|addk[:k]|wittenbell|kn[:discount]")
+                .takes_value(true))
+            .arg(Arg::with_name("reverse")
+                .long("reverse")
+                .help("also train reverse chains for generate --end-with and --keyword")
+                .takes_value(false))
+            .arg(Arg::with_name("memory")
+                .long("memory")
+                .help("train in memory without writing a db, reporting held out perplexity with --holdout")
+                .takes_value(false)))
+
+        // subword tokenizer
+        .subcommand(App::new("tokenizer")
+            .about("byte-pair encoding, splits rare words into pieces seen elsewhere")
+            .subcommand(App::new("learn")
+                .about("learn merges from a corpus into a db, train on the db afterwards to use them")
+                .arg(Arg::with_name("path")
+                    .short("p")
+                    .help("path to corpus")
+                    .required(true)
+                    .takes_value(true))
+                .arg(Arg::with_name("dbpath")
+                    .short("d")
+                    .help("path to db")
+                    .takes_value(true))
+                .arg(Arg::with_name("merges")
+                    .short("n")
+                    .long("merges")
+                    .help("number of merges to learn (default 1000)")
+                    .takes_value(true)))
+            .subcommand(App::new("encode")
+                .about("show how a db's tokenizer splits some text")
+                .arg(Arg::with_name("dbpath")
+                    .short("d")
+                    .help("path to db")
+                    .takes_value(true))
+                .arg(Arg::with_name("text")
+                    .required(true)
+                    .index(1))))
+
+        // group map inspection
+        .subcommand(App::new("groups")
+            .about("show which group, i.e. n-gram order, each word of a trained db is in")
+            .arg(Arg::with_name("dbpath")
+                .short("d")
+                .help("path to db")
+                .takes_value(true))
+            .arg(Arg::with_name("group")
+                .short("g")
+                .help("only show words in this group")
+                .takes_value(true))
+            .arg(Arg::with_name("summary")
+                .long("summary")
+                .help("number of words in each group instead of the words themselves")
+                .takes_value(false))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("output format (default text)")
+                .possible_values(&["text", "json", "csv", "tsv"])
+                .takes_value(true)))
+
+        // model evaluation
+        .subcommand(App::new("evaluate")
+            .about("score held out text with a trained model")
+            .arg(Arg::with_name("dbpath")
+                .short("d")
+                .help("path to db")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("path")
+                .short("p")
+                .help("path to held out corpus, defaults to the files put aside by train --holdout")
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("backoff[:discount]|addk[:k]|wittenbell|kn[:discount], defaults to the one the db was trained with")
+                .takes_value(true)))
+
+        // language model export
+        .subcommand(App::new("export")
+            .about("export trained chains as an ARPA back-off language model")
+            .arg(Arg::with_name("dbpath")
+                .short("d")
+                .help("path to db")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .help("path to write the ARPA file to")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("backoff[:discount]|addk[:k]|wittenbell|kn[:discount], defaults to the one the db was trained with")
+                .takes_value(true)))
+
+        // compiled models for generation
+        .subcommand(App::new("compile")
+            .about("compile a trained db into a single memory-mapped file for fast generation")
+            .arg(Arg::with_name("dbpath")
+                .short("d")
+                .help("path to db")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .help("path to write the compiled model to, pass it to generate -d in place of the db")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("backoff[:discount]|addk[:k]|wittenbell|kn[:discount], defaults to the one the db was trained with")
+                .takes_value(true)))
+
+        // shrinking a trained db
+        .subcommand(App::new("prune")
+            .about("remove rare or uninformative n-grams from a trained db and compact it")
+            .arg(Arg::with_name("dbpath")
+                .short("d")
+                .help("path to db")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("min_count")
+                .long("min-count")
+                .help("remove successors seen fewer than this many times after their context")
+                .takes_value(true))
+            .arg(Arg::with_name("entropy")
+                .long("entropy")
+                .help("remove n-grams whose weighted log ratio to the backed off estimate is below this, e.g. 1e-7")
+                .takes_value(true))
+            .arg(Arg::with_name("path")
+                .short("p")
+                .help("path to held out corpus to report perplexity on, defaults to the files put aside by train --holdout")
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("backoff[:discount]|addk[:k]|wittenbell|kn[:discount], defaults to the one the db was trained with")
                 .takes_value(true)))
 
         // generation
@@ -61,12 +356,47 @@ fn main() {
             .about("generate text")
             .arg(Arg::with_name("dbpath")
                 .short("d")
-                .help("path to db")
+                .help("path to db or compiled model, repeat to blend several models")
                 .required(true)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("weight")
+                .short("w")
+                .help("weight of each db in the blend, in the same order as -d (default equal)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("switch")
+                .long("switch")
+                .help("blend models for every word, or pick one per sentence or paragraph")
+                .possible_values(&["word", "sentence", "paragraph"])
+                .takes_value(true))
+            .arg(Arg::with_name("end")
+                .long("end-with")
+                .help("phrase to end the text with, needs a db trained with --reverse")
+                .takes_value(true)
+                .conflicts_with("keyword"))
+            .arg(Arg::with_name("keyword")
+                .long("keyword")
+                .help("word or phrase to grow the text outwards from, needs a db trained with --reverse")
+                .takes_value(true))
+            .arg(Arg::with_name("unk-model")
+                .long("unk-model")
+                .help("character model db to make up words with where the model says <unk>, otherwise rare words from training are used")
                 .takes_value(true))
             .arg(Arg::with_name("length")
                 .short("l")
                 .help("how many words to generate")
+                .takes_value(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .help("also write the generated text to this file, e.g. for analyse style")
+                .takes_value(true))
+            .arg(Arg::with_name("smoothing")
+                .short("s")
+                .long("smoothing")
+                .help("backoff[:discount]|addk[:k]|wittenbell|kn[:discount], defaults to the one the db was trained with")
                 .takes_value(true)))
 
         // getting data
@@ -95,11 +425,25 @@ fn main() {
 
         .get_matches();
 
-    match matches.subcommand() {
-        ("analyse", Some(args)) => train::analyse_cmd(args),
-        ("train", Some(args)) => train::train_cmd(args),
-        ("generate", Some(args)) => generate::run_cmd(args),
-        ("crawl", Some(args)) => crawl::crawl_cmd(args),
-        _ => eprintln!("{}", matches.usage())
+    let result = match matches.subcommand() {
+        ("analyse", Some(args)) => cmd::analyse::analyse_cmd(args),
+        ("train", Some(args)) => cmd::train::train_cmd(args),
+        ("groups", Some(args)) => cmd::train::groups_cmd(args),
+        ("tokenizer", Some(args)) => cmd::train::tokenizer_cmd(args),
+        ("evaluate", Some(args)) => cmd::model::evaluate_cmd(args),
+        ("export", Some(args)) => cmd::model::export_cmd(args),
+        ("prune", Some(args)) => cmd::model::prune_cmd(args),
+        ("compile", Some(args)) => cmd::model::compile_cmd(args),
+        ("generate", Some(args)) => cmd::generate::run_cmd(args),
+        ("crawl", Some(args)) => cmd::crawl::crawl_cmd(args),
+        _ => {
+            eprintln!("{}", matches.usage());
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+
+use crate::model::backoff::UNKNOWN;
+use crate::model::smoothing::Estimator;
+
+// Write the model as an ARPA back-off language model. Probabilities and back-off weights are log10
+// as the format expects, n-grams are sorted within each order so exports of the same db diff cleanly.
+// Interpolated estimates are written the usual way: seen n-grams get their interpolated probability
+// and each context a weight that leaves the remaining mass to the shorter context.
+pub fn write<W: Write>(model: &dyn Estimator, out: &mut W) -> io::Result<()> {
+    let counts = model.counts();
+    let top = counts.order;
+
+    let mut sets: Vec<HashSet<Vec<String>>> = vec![HashSet::new(); counts.order];
+    counts.contexts.iter().for_each(|(k, v)| {
+        v.keys().for_each(|w| {
+            let mut ngram = k.clone();
+            ngram.push(w.to_string());
+            sets[k.len()].insert(ngram);
+        });
+    });
+    sets[0].insert(vec![UNKNOWN.to_string()]);
+
+    // the chains only hold contexts of their own length, so the prefix of a stored n-gram isn't
+    // necessarily stored itself. ARPA readers expect it to be, it gets its backed-off probability.
+    for i in (1..counts.order).rev() {
+        let prefixes: Vec<Vec<String>> = sets[i].iter()
+            .map(|n| n[..i].to_vec())
+            .collect();
+        prefixes.into_iter().for_each(|p| {
+            sets[i - 1].insert(p);
+        });
+    }
+
+    let orders: Vec<Vec<Vec<String>>> = sets.into_iter()
+        .map(|s| {
+            let mut o: Vec<Vec<String>> = s.into_iter().collect();
+            o.sort();
+            return o;
+        })
+        .collect();
+
+    writeln!(out, "\\data\\")?;
+    for (i, o) in orders.iter().enumerate() {
+        writeln!(out, "ngram {}={}", i + 1, o.len())?;
+    }
+
+    for (i, o) in orders.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "\\{}-grams:", i + 1)?;
+
+        for ngram in o {
+            let (word, context) = ngram.split_last().unwrap();
+            write!(out, "{:.6}\t{}", log10(model.arpa_prob(context, word)), ngram.join(" "))?;
+
+            // highest order n-grams never back off
+            if i + 1 < top && counts.successors(ngram).is_some() {
+                write!(out, "\t{:.6}", log10(model.backoff_weight(ngram)))?;
+            }
+            writeln!(out)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "\\end\\")?;
+    return Ok(());
+}
+
+// ARPA uses -99 for "impossible"
+fn log10(p: f64) -> f64 {
+    if p <= 0.0 {
+        return -99.0;
+    }
+    return p.log10();
+}
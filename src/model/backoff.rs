@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use crate::model::Counts;
+use crate::model::smoothing::Estimator;
+
+pub const DEFAULT_DISCOUNT: f64 = 0.5;
+
+// word used for everything outside the training vocabulary
+pub const UNKNOWN: &str = "<unk>";
+
+// Back-off language model over the chain counts using absolute discounting: every seen n-gram
+// gives up `discount` of its count, and the mass freed up in a context is handed to the shorter
+// context through that context's back-off weight.
+pub struct Backoff<'a> {
+    counts: &'a Counts,
+    discount: f64,
+
+    // total successor count per context
+    totals: HashMap<&'a [String], u32>,
+    weights: HashMap<&'a [String], f64>,
+}
+
+pub fn new(counts: &Counts, discount: f64) -> Backoff<'_> {
+    let mut model = Backoff {
+        counts,
+        discount,
+        totals: HashMap::new(),
+        weights: HashMap::new(),
+    };
+
+    counts.contexts.iter().for_each(|(k, v)| {
+        model.totals.insert(k.as_slice(), v.values().sum());
+    });
+
+    // back-off weights depend on the probabilities of the shorter contexts, so go shortest first
+    let mut contexts: Vec<&Vec<String>> = counts.contexts.keys()
+        .filter(|k| !k.is_empty())
+        .collect();
+    contexts.sort_by_key(|k| k.len());
+
+    for k in contexts {
+        let weight = model.backoff_weight(k);
+        model.weights.insert(k.as_slice(), weight);
+    }
+
+    return model;
+}
+
+impl<'a> Estimator for Backoff<'a> {
+    fn counts(&self) -> &Counts {
+        return self.counts;
+    }
+
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        let context = self.counts.history(context);
+
+        match self.counts.successors(context) {
+            Some(successors) => {
+                let total = *self.totals.get(context).unwrap() as f64;
+                if let Some(&c) = successors.get(word) {
+                    return (c as f64 - self.discount) / total;
+                }
+
+                if context.is_empty() {
+                    // everything that was discounted from the unigrams goes to unknown words
+                    return self.discount * successors.len() as f64 / total;
+                }
+
+                let weight = match self.weights.get(context) {
+                    Some(w) => *w,
+                    // still being built, see new()
+                    None => 1.0,
+                };
+                return weight * self.prob(&context[1..], word);
+            }
+            None => {
+                if context.is_empty() {
+                    return 0.0;
+                }
+                return self.prob(&context[1..], word);
+            }
+        }
+    }
+}
@@ -0,0 +1,426 @@
+extern crate memmap;
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use self::memmap::Mmap;
+
+use crate::error::{self, Error};
+use crate::model::smoothing::Estimator;
+use crate::model::store::{self, ChainStore};
+use crate::model::Counts;
+
+// A model compiled out of a db for generation: read-only, memory mapped and queried in place.
+//
+//   header   magic, version, then offsets of the meta, vocabulary, forward and reverse sections
+//   meta     bincode (meta key -> value, <unk> class -> rare words), the only part decoded on load
+//   vocab    word count, offsets into the strings, the strings; sorted so words are found by
+//            binary search and a word's id is its position
+//   table    order, sizes and the probability of an unseen word, then contexts (start and length in the id array, start and length in
+//            the successor array, back-off weight) sorted by their word ids, the id array, then
+//            successors (word id, probability) sorted by word id
+//
+// Probabilities are already smoothed, stored ARPA style (see arpa::write): seen successors get
+// their probability and each context the weight that leaves the rest to the shorter context.
+// Everything is little endian.
+const MAGIC: &[u8; 4] = b"RGMC";
+const VERSION: u32 = 1;
+const HEADER: usize = 8 + 8 * 5;
+const CONTEXT: usize = 4 * 5;
+const SUCCESSOR: usize = 4 * 2;
+
+// meta copied into the compiled file, generate needs them to split and join tokens
+const META: [&str; 5] = [super::UNIT, super::BPE_MERGES, super::SENTENCES, super::UNKNOWN_CLASSES, super::REVERSE];
+
+pub struct Compiled {
+    map: Mmap,
+    vocab: usize,
+    forward: usize,
+    reverse: usize,
+
+    // meta and rare words of the db it was compiled from, so the usual model:: lookups work on it
+    pub meta: store::Memory,
+}
+
+// Compile /model/, and /reverse/ if there is one, into the file at /path/. /store/ is the db they
+// came from.
+pub fn write(store: &dyn ChainStore, model: &dyn Estimator, reverse: Option<&dyn Estimator>, smoothing: &str,
+             path: &str) -> Result<(), Error> {
+    let mut words: BTreeSet<&str> = BTreeSet::new();
+    for counts in Some(model.counts()).into_iter().chain(reverse.map(|r| r.counts())) {
+        counts.vocabulary.iter().for_each(|w| {
+            words.insert(w);
+        });
+        counts.contexts.iter().for_each(|(k, v)| {
+            k.iter().chain(v.keys()).for_each(|w| {
+                words.insert(w);
+            });
+        });
+    }
+    let words: Vec<&str> = words.into_iter().collect();
+    let ids: HashMap<&str, u32> = words.iter().enumerate().map(|(i, w)| (*w, i as u32)).collect();
+
+    let mut meta: HashMap<String, Vec<u8>> = HashMap::new();
+    for k in META.iter() {
+        if let Some(v) = store.meta(k)? {
+            meta.insert(k.to_string(), v);
+        }
+    }
+    meta.insert(super::SMOOTHING.to_string(), bincode::serialize(smoothing)?);
+    let meta = bincode::serialize(&(meta, store.rare()?))?;
+
+    let vocab = vocab_section(&words);
+    let forward = table_section(model, &ids);
+    let backward = reverse.map(|r| table_section(r, &ids)).unwrap_or_default();
+
+    // written alongside and renamed over the old file, which may be mapped by a generate still running
+    let failed = format!("failed to write {}", path);
+    let tmp = format!("{}.tmp", path);
+    let mut out = BufWriter::new(File::create(&tmp).map_err(error::io(&failed))?);
+    let meta_at = HEADER;
+    let vocab_at = meta_at + meta.len();
+    let forward_at = vocab_at + vocab.len();
+    let reverse_at = if reverse.is_some() { forward_at + forward.len() } else { 0 };
+
+    let mut header: Vec<u8> = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    for offset in [meta_at, meta.len(), vocab_at, forward_at].iter().chain(Some(reverse_at).iter()) {
+        // meta length aside these are all offsets from the start of the file
+        header.extend_from_slice(&(*offset as u64).to_le_bytes());
+    }
+    [header, meta, vocab, forward, backward].iter()
+        .try_for_each(|section| out.write_all(section))
+        .and_then(|_| out.flush())
+        .map_err(error::io(&failed))?;
+    drop(out);
+
+    return fs::rename(&tmp, path).map_err(error::io(&failed));
+}
+
+fn vocab_section(words: &Vec<&str>) -> Vec<u8> {
+    let mut section = Vec::new();
+    section.extend_from_slice(&(words.len() as u32).to_le_bytes());
+
+    let mut offset = 0u32;
+    section.extend_from_slice(&offset.to_le_bytes());
+    for w in words {
+        offset += w.len() as u32;
+        section.extend_from_slice(&offset.to_le_bytes());
+    }
+    words.iter().for_each(|w| section.extend_from_slice(w.as_bytes()));
+
+    return section;
+}
+
+fn table_section(model: &dyn Estimator, ids: &HashMap<&str, u32>) -> Vec<u8> {
+    let counts: &Counts = model.counts();
+    let top = counts.order;
+
+    let mut contexts: Vec<(Vec<u32>, &Vec<String>)> = counts.contexts.keys()
+        .map(|k| (k.iter().map(|w| ids[w.as_str()]).collect(), k))
+        .collect();
+    contexts.sort();
+
+    let mut records: Vec<u8> = Vec::with_capacity(contexts.len() * CONTEXT);
+    let mut context_ids: Vec<u8> = Vec::new();
+    let mut successors: Vec<u8> = Vec::new();
+    let (mut id_count, mut successor_count) = (0u32, 0u32);
+
+    for (key, context) in contexts.iter() {
+        let mut seen: Vec<(u32, &String)> = counts.contexts[*context].keys().map(|w| (ids[w.as_str()], w)).collect();
+        seen.sort();
+
+        let backoff = model.backoff_weight(context);
+
+        [id_count, key.len() as u32, successor_count, seen.len() as u32].iter()
+            .for_each(|x| records.extend_from_slice(&x.to_le_bytes()));
+        records.extend_from_slice(&(backoff as f32).to_le_bytes());
+
+        key.iter().for_each(|id| context_ids.extend_from_slice(&id.to_le_bytes()));
+        id_count += key.len() as u32;
+
+        for (id, w) in seen.iter() {
+            successors.extend_from_slice(&id.to_le_bytes());
+            successors.extend_from_slice(&(model.arpa_prob(context, w) as f32).to_le_bytes());
+        }
+        successor_count += seen.len() as u32;
+    }
+
+    // no token is ever empty, so this is what the model gives a word it never saw
+    let unseen = if top == 1 { model.prob(&[], "") } else { model.lower(&[], "") };
+
+    let mut section = Vec::new();
+    [top as u32, contexts.len() as u32, id_count, successor_count].iter()
+        .for_each(|x| section.extend_from_slice(&x.to_le_bytes()));
+    section.extend_from_slice(&(unseen as f32).to_le_bytes());
+    section.extend(records);
+    section.extend(context_ids);
+    section.extend(successors);
+    return section;
+}
+
+pub fn open(path: &str) -> Result<Compiled, Error> {
+    let opening = format!("couldnt open compiled model {}", path);
+    let file = File::open(path).map_err(error::io(&opening))?;
+    // the file is only ever read, and compile renames a new one into place rather than changing it
+    let map = unsafe { Mmap::map(&file) }.map_err(error::io(&opening))?;
+
+    if map.len() < HEADER || &map[..4] != MAGIC {
+        return Err(Error::Format(format!("{} is not a compiled model", path)));
+    }
+    if u32_at(&map, 4) != VERSION {
+        return Err(Error::Format(format!("{} was compiled by a different version, compile it again", path)));
+    }
+
+    let at = |i: usize| u64::from_le_bytes(map[8 + i * 8..16 + i * 8].try_into().unwrap()) as usize;
+    let (meta_at, meta_len, vocab, forward, reverse) = (at(0), at(1), at(2), at(3), at(4));
+    let truncated = || Error::Format(format!("{} is cut short or damaged, compile it again", path));
+    if meta_at.checked_add(meta_len).map(|end| end > map.len()).unwrap_or(true) {
+        return Err(truncated());
+    }
+    let (values, rare): (HashMap<String, Vec<u8>>, HashMap<String, Vec<String>>) =
+        bincode::deserialize(&map[meta_at..meta_at + meta_len])
+            .map_err(|e| Error::Format(format!("couldnt read the meta of {}: {}", path, e)))?;
+
+    let meta = store::memory();
+    for (k, v) in values {
+        meta.set_meta(&k, v)?;
+    }
+    for (class, words) in rare.iter() {
+        meta.set_rare(class, words)?;
+    }
+
+    let compiled = Compiled { map, vocab, forward, reverse, meta };
+    if !compiled.fits() {
+        return Err(truncated());
+    }
+    return Ok(compiled);
+}
+
+impl Compiled {
+    pub fn forward(&self) -> Table<'_> {
+        return self.table(self.forward);
+    }
+
+    pub fn reverse(&self) -> Option<Table<'_>> {
+        if self.reverse == 0 {
+            return None;
+        }
+        return Some(self.table(self.reverse));
+    }
+
+    // whether the vocabulary and tables lie within the file, and every record and id in them points
+    // where it should, so looking things up in them can't run off the end
+    fn fits(&self) -> bool {
+        let data: &[u8] = &self.map;
+        let within = |at: usize, len: usize| at.checked_add(len).map(|end| end <= data.len()).unwrap_or(false);
+        if !within(self.vocab, 4) {
+            return false;
+        }
+
+        let words = u32_at(data, self.vocab) as usize;
+        let offsets = self.vocab + 4;
+        if !within(offsets, (words + 1) * 4) {
+            return false;
+        }
+        let strings = offsets + (words + 1) * 4;
+        if !within(strings, u32_at(data, offsets + words * 4) as usize) {
+            return false;
+        }
+        let valid = (0..words).all(|i| {
+            let (start, end) = (u32_at(data, offsets + i * 4) as usize, u32_at(data, offsets + i * 4 + 4) as usize);
+            start <= end && within(strings + start, end - start)
+                && std::str::from_utf8(&data[strings + start..strings + end]).is_ok()
+        });
+        if !valid {
+            return false;
+        }
+
+        let tables = if self.reverse == 0 { vec![self.forward] } else { vec![self.forward, self.reverse] };
+        return tables.into_iter().all(|at| {
+            if !within(at, 20) {
+                return false;
+            }
+            let (order, contexts, ids, successors) = (u32_at(data, at) as usize, u32_at(data, at + 4) as usize,
+                                                      u32_at(data, at + 8) as usize, u32_at(data, at + 12) as usize);
+            if order == 0 || !within(at + 20, contexts * CONTEXT + ids * 4 + successors * SUCCESSOR) {
+                return false;
+            }
+
+            // each context's ids and successors lie within their arrays, and every id is a word
+            let records = at + 20;
+            let context_ids = records + contexts * CONTEXT;
+            let successor_at = context_ids + ids * 4;
+            (0..contexts).all(|i| {
+                let r = records + i * CONTEXT;
+                let field = |f: usize| u32_at(data, r + f * 4) as usize;
+                field(0) + field(1) <= ids && field(2) + field(3) <= successors
+            })
+                && (0..ids).all(|i| (u32_at(data, context_ids + i * 4) as usize) < words)
+                && (0..successors).all(|i| (u32_at(data, successor_at + i * SUCCESSOR) as usize) < words)
+        });
+    }
+
+    fn table(&self, at: usize) -> Table<'_> {
+        let data: &[u8] = &self.map;
+        let words = u32_at(data, self.vocab) as usize;
+        let contexts = u32_at(data, at + 4) as usize;
+        let ids = u32_at(data, at + 8) as usize;
+
+        let offsets = self.vocab + 4;
+        let strings = offsets + (words + 1) * 4;
+        let records = at + 20;
+        let context_ids = records + contexts * CONTEXT;
+        let successors = context_ids + ids * 4;
+
+        return Table {
+            data,
+            order: u32_at(data, at) as usize,
+            unseen: f32::from_le_bytes(data[at + 16..at + 20].try_into().unwrap()) as f64,
+            words,
+            offsets,
+            strings,
+            contexts,
+            records,
+            context_ids,
+            successors,
+        };
+    }
+}
+
+// one direction of a compiled model, positions are offsets into the mapped file
+pub struct Table<'a> {
+    data: &'a [u8],
+    pub order: usize,
+    unseen: f64,
+    words: usize,
+    offsets: usize,
+    strings: usize,
+    pub contexts: usize,
+    records: usize,
+    context_ids: usize,
+    successors: usize,
+}
+
+impl<'a> Table<'a> {
+    fn word(&self, id: usize) -> &'a str {
+        let start = self.strings + u32_at(self.data, self.offsets + id * 4) as usize;
+        let end = self.strings + u32_at(self.data, self.offsets + id * 4 + 4) as usize;
+        return std::str::from_utf8(&self.data[start..end]).unwrap();
+    }
+
+    fn id(&self, word: &str) -> Option<u32> {
+        let (mut lo, mut hi) = (0, self.words);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.word(mid).cmp(word) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(mid as u32),
+            }
+        }
+        return None;
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        return self.id(word).is_some();
+    }
+
+    // (ids, successor start, successor count, back-off weight) of the ith context
+    fn context(&self, i: usize) -> (&'a [u8], usize, usize, f64) {
+        let at = self.records + i * CONTEXT;
+        let start = self.context_ids + u32_at(self.data, at) as usize * 4;
+        let len = u32_at(self.data, at + 4) as usize;
+        let backoff = f32::from_le_bytes(self.data[at + 16..at + 20].try_into().unwrap());
+        return (&self.data[start..start + len * 4], u32_at(self.data, at + 8) as usize,
+                u32_at(self.data, at + 12) as usize, backoff as f64);
+    }
+
+    fn find(&self, key: &[u32]) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.contexts);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let (ids, _, _, _) = self.context(mid);
+            let stored = ids.chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap()));
+            match stored.cmp(key.iter().cloned()) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        return None;
+    }
+
+    // probability of /word/ after the ith context, if it was seen there
+    fn successor(&self, i: usize, word: u32) -> Option<f64> {
+        let (_, start, len, _) = self.context(i);
+        let (mut lo, mut hi) = (start, start + len);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let at = self.successors + mid * SUCCESSOR;
+            match u32_at(self.data, at).cmp(&word) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(f32::from_le_bytes(self.data[at + 4..at + 8].try_into().unwrap()) as f64),
+            }
+        }
+        return None;
+    }
+
+    // ids of the part of a context the model can use, None for words it has never seen
+    fn history(&self, context: &[String]) -> Vec<Option<u32>> {
+        let start = if context.len() >= self.order { context.len() + 1 - self.order } else { 0 };
+        return context[start..].iter().map(|w| self.id(w)).collect();
+    }
+
+    // P(word | context), backing off through shorter contexts the way an ARPA model does
+    pub fn prob(&self, context: &[String], word: &str) -> f64 {
+        let word = self.id(word);
+        let history = self.history(context);
+
+        // contexts with a word the model never saw can't have been stored
+        let first = history.iter().rposition(|w| w.is_none()).map(|p| p + 1).unwrap_or(0);
+        let mut weight = 1.0;
+        for s in first..=history.len() {
+            let key: Vec<u32> = history[s..].iter().map(|w| w.unwrap()).collect();
+            if let Some(i) = self.find(&key) {
+                if let Some(p) = word.and_then(|w| self.successor(i, w)) {
+                    return weight * p;
+                }
+                if !key.is_empty() {
+                    weight *= self.context(i).3;
+                }
+            }
+        }
+        return weight * self.unseen;
+    }
+
+    // words seen after exactly this context, cut down to the model order
+    pub fn followers(&self, context: &[String]) -> Vec<&'a str> {
+        let key: Option<Vec<u32>> = self.history(context).into_iter().collect();
+        let i = match key.and_then(|k| self.find(&k)) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+
+        let (_, start, len, _) = self.context(i);
+        return (start..start + len)
+            .map(|s| self.word(u32_at(self.data, self.successors + s * SUCCESSOR) as usize))
+            .collect();
+    }
+
+    // the words of the ith context
+    pub fn words(&self, i: usize) -> Vec<String> {
+        let (ids, _, _, _) = self.context(i);
+        return ids.chunks(4)
+            .map(|c| self.word(u32::from_le_bytes(c.try_into().unwrap()) as usize).to_string())
+            .collect();
+    }
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    return u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+}
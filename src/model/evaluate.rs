@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use crate::model::smoothing::Estimator;
+use crate::train::analyse::SENTENCE_START;
+use crate::train::data::read_file;
+
+pub struct Report {
+    pub tokens: u64,
+    pub oov: u64,
+
+    // log10 likelihood of all in-vocabulary tokens
+    pub log_likelihood: f64,
+}
+
+impl Report {
+    pub fn scored(&self) -> u64 {
+        return self.tokens - self.oov;
+    }
+
+    pub fn per_token(&self) -> f64 {
+        if self.scored() == 0 {
+            return 0.0;
+        }
+        return self.log_likelihood / self.scored() as f64;
+    }
+
+    pub fn perplexity(&self) -> f64 {
+        return 10f64.powf(-self.per_token());
+    }
+
+    pub fn oov_rate(&self) -> f64 {
+        if self.tokens == 0 {
+            return 0.0;
+        }
+        return self.oov as f64 / self.tokens as f64;
+    }
+}
+
+// Score every held out document with the model. Each token is predicted from up to order - 1
+// preceding tokens of the same document; out of vocabulary tokens are counted but left out of the
+// likelihood, as is usual for closed vocabulary models. Documents are split with /tokenize/, the same
+// way the model was trained; sentence markers are scored too, bar the <s> sentences start with, which
+// are given rather than predicted.
+pub fn evaluate(model: &dyn Estimator, files: &Vec<PathBuf>, tokenize: &dyn Fn(&str) -> Vec<String>) -> Report {
+    let mut report = Report {
+        tokens: 0,
+        oov: 0,
+        log_likelihood: 0.0,
+    };
+
+    let history = model.counts().order - 1;
+
+    for file in files {
+        let data = match read_file(file) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("skipping {:?}: {}", file, e);
+                continue;
+            }
+        };
+        let words = tokenize(&data);
+
+        for (i, w) in words.iter().enumerate() {
+            if w == SENTENCE_START {
+                continue;
+            }
+
+            report.tokens += 1;
+            if !model.counts().contains(w) {
+                report.oov += 1;
+                continue;
+            }
+
+            let start = if i > history { i - history } else { 0 };
+            report.log_likelihood += model.prob(&words[start..i], w).log10();
+        }
+    }
+
+    return report;
+}
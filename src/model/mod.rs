@@ -0,0 +1,335 @@
+extern crate sled;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{self, Error};
+use crate::train::analyse::{char_corpus, create_corpus, sentence_corpus};
+use crate::train::bpe::{self, Bpe};
+use crate::train::vocab::{self, Vocabulary};
+
+use self::compiled::Compiled;
+use self::prune::Pruned;
+use self::smoothing::Smoothing;
+use self::store::ChainStore;
+
+mod arpa;
+pub mod backoff;
+pub mod compiled;
+pub mod evaluate;
+pub mod prune;
+pub mod smoothing;
+pub mod store;
+
+// n-gram counts recovered from the trained group chains. Each chain only stores contexts of its
+// own length, so every stored n-gram is also counted under all of its shorter suffixes; the empty
+// context then holds the unigram counts.
+pub struct Counts {
+    pub contexts: HashMap<Vec<String>, HashMap<String, u32>>,
+
+    // every word that was assigned a group, not all of them end up being predicted by a chain
+    pub vocabulary: HashSet<String>,
+
+    // highest n-gram order, i.e. longest context + 1
+    pub order: usize,
+}
+
+impl Counts {
+    pub fn successors(&self, context: &[String]) -> Option<&HashMap<String, u32>> {
+        return self.contexts.get(context);
+    }
+
+    // the part of a context the model can actually use
+    pub fn history<'c>(&self, context: &'c [String]) -> &'c [String] {
+        if context.len() >= self.order {
+            return &context[context.len() + 1 - self.order..];
+        }
+        return context;
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        return self.vocabulary.contains(word);
+    }
+}
+
+pub fn load(store: &dyn ChainStore) -> Result<Counts, Error> {
+    return load_chains(store, false);
+}
+
+// counts of the reverse chains, i.e. a model of the corpus read backwards. None unless the db was
+// trained with --reverse.
+pub fn load_reverse(store: &dyn ChainStore) -> Result<Option<Counts>, Error> {
+    if get_meta::<bool>(store, REVERSE)? != Some(true) {
+        return Ok(None);
+    }
+    return load_chains(store, true).map(Some);
+}
+
+fn load_chains(store: &dyn ChainStore, reverse: bool) -> Result<Counts, Error> {
+    let vocabulary: HashSet<String> = store.groups()?.into_iter().map(|(w, _)| w).collect();
+
+    let mut contexts: HashMap<Vec<String>, HashMap<String, u32>> = HashMap::new();
+    let mut order: usize = 1;
+
+    for g in chain_orders(store)? {
+        store.each_context(g, reverse, &mut |key, value| {
+            if key.len() + 1 > order {
+                order = key.len() + 1;
+            }
+
+            for s in 0..=key.len() {
+                let successors = contexts.entry(key[s..].to_vec()).or_insert_with(HashMap::new);
+                value.iter().for_each(|(w, c)| *successors.entry(w.to_string()).or_insert(0) += c);
+            }
+        })?;
+    }
+
+    return Ok(Counts {
+        contexts,
+        vocabulary,
+        order,
+    });
+}
+
+// orders of the chains in a db. chains cut short at the end of documents needn't match any group,
+// newer dbs list them all
+fn chain_orders(store: &dyn ChainStore) -> Result<Vec<u32>, Error> {
+    if let Some(chains) = get_meta::<Vec<u32>>(store, CHAINS)? {
+        return Ok(chains);
+    }
+
+    let mut orders: Vec<u32> = store.groups()?.into_iter().map(|(_, g)| g).collect();
+    orders.sort();
+    orders.dedup();
+    return Ok(orders);
+}
+
+// A trained model to score, export, compile, prune or generate with: a db, or a single file compiled
+// out of one. Compiled models were smoothed when compiled and can only be generated with.
+pub struct Model {
+    path: String,
+    kind: Kind,
+    smoothing: Smoothing,
+}
+
+pub enum Kind {
+    Db(store::Sled),
+    Compiled(Compiled),
+}
+
+// open the model at /path/, a compiled model if it's a file and a db otherwise
+pub fn open(path: &str) -> Result<Model, Error> {
+    let kind = if Path::new(path).is_file() {
+        Kind::Compiled(compiled::open(path)?)
+    } else {
+        Kind::Db(store::open(path)?)
+    };
+
+    let mut model = Model { path: path.to_string(), kind, smoothing: Smoothing::Backoff(backoff::DEFAULT_DISCOUNT) };
+    model.smoothing = smoothing::select(model.meta(), None)?;
+    return Ok(model);
+}
+
+impl Model {
+    // smooth with something other than what the db was trained for. a compiled model can only be
+    // asked for what it was compiled with.
+    pub fn smoothing(mut self, smoothing: Smoothing) -> Result<Model, Error> {
+        if let Kind::Compiled(_) = self.kind {
+            if smoothing != self.smoothing {
+                return Err(Error::Model(format!("{} was compiled with {} smoothing, compile it again for {}", self.path,
+                                   self.smoothing, smoothing)));
+            }
+        }
+        self.smoothing = smoothing;
+        return Ok(self);
+    }
+
+    pub fn path(&self) -> &str {
+        return &self.path;
+    }
+
+    pub fn kind(&self) -> &Kind {
+        return &self.kind;
+    }
+
+    pub fn smoothed_with(&self) -> Smoothing {
+        return self.smoothing;
+    }
+
+    // where is_char_model, load_bpe and the like look, the db or what was copied into the compiled file
+    pub fn meta(&self) -> &dyn ChainStore {
+        return match &self.kind {
+            Kind::Db(db) => db,
+            Kind::Compiled(c) => &c.meta,
+        };
+    }
+
+    // files `train --holdout` put aside
+    pub fn held_out(&self) -> Result<Option<Vec<PathBuf>>, Error> {
+        return held_out(self.meta());
+    }
+
+    fn db(&self, doing: &str) -> Result<&store::Sled, Error> {
+        return match &self.kind {
+            Kind::Db(db) => Ok(db),
+            Kind::Compiled(_) => Err(Error::Model(format!("{} is a compiled model, {} needs the db it was compiled from",
+                                                          self.path, doing))),
+        };
+    }
+
+    pub fn evaluate(&self, files: &Vec<PathBuf>) -> Result<evaluate::Report, Error> {
+        let db = self.db("evaluate")?;
+        let counts = load(db)?;
+        let model = smoothing::build(&counts, self.smoothing);
+        return Ok(evaluate::evaluate(&*model, files, &*tokenizer(db)?));
+    }
+
+    // perplexity of /files/, see evaluate
+    pub fn perplexity(&self, files: &Vec<PathBuf>) -> Result<f64, Error> {
+        return perplexity(self.db("perplexity")?, self.smoothing, files);
+    }
+
+    // write an ARPA back-off model to /output/, returns its order
+    pub fn export(&self, output: &str) -> Result<usize, Error> {
+        let db = self.db("export")?;
+        let counts = load(db)?;
+        let model = smoothing::build(&counts, self.smoothing);
+
+        let file = File::create(output).map_err(error::io(&format!("Could not open file {} for writing", output)))?;
+        let mut writer = BufWriter::new(file);
+        arpa::write(&*model, &mut writer).and_then(|_| writer.flush())
+            .map_err(error::io(&format!("failed to write {}", output)))?;
+        return Ok(counts.order);
+    }
+
+    // smooth the chains once and write them to a file generate can map straight into memory, returns
+    // its order
+    pub fn compile(&self, output: &str) -> Result<usize, Error> {
+        let db = self.db("compile")?;
+
+        // the unseen words of a context get a share of the shorter context's probabilities, add-k has
+        // no shorter context to share
+        if let Smoothing::AddK(_) = self.smoothing {
+            return Err(Error::Model("add-k smoothing doesn't back off so it can't be compiled, pick another with -s".to_string()));
+        }
+
+        let counts = load(db)?;
+        let model = smoothing::build(&counts, self.smoothing);
+        let reverse_counts = load_reverse(db)?;
+        let reverse = reverse_counts.as_ref().map(|c| smoothing::build(c, self.smoothing));
+
+        compiled::write(db, &*model, reverse.as_ref().map(|r| &**r), &self.smoothing.to_string(), output)?;
+        return Ok(counts.order);
+    }
+
+    // Prune the db (see prune::prune) and compact it. The db has to be closed to be compacted, so the
+    // model is used up and the pruned one opened again in its place.
+    pub fn prune(self, min_count: u32, entropy: Option<f64>) -> Result<(Pruned, Model), Error> {
+        let db = self.db("prune")?;
+        let before = prune::size(Path::new(&self.path)).map_err(error::io(&format!("couldnt size db {}", self.path)))?;
+
+        let mut pruned = prune::prune(db, min_count, entropy.map(|t| (t, self.smoothing)))?;
+        db.flush()?;
+
+        let (path, smoothing) = (self.path.clone(), self.smoothing);
+        drop(self);
+        prune::compact(&path)?;
+
+        pruned.size = (before, prune::size(Path::new(&path)).map_err(error::io(&format!("couldnt size db {}", path)))?);
+        let kind = Kind::Db(store::reopen(&path)?);
+        return Ok((pruned, Model { path, kind, smoothing }));
+    }
+}
+
+// files `train --holdout` put aside, see train::Persistent::holdout
+pub fn held_out(store: &dyn ChainStore) -> Result<Option<Vec<PathBuf>>, Error> {
+    return Ok(get_meta::<Vec<String>>(store, HOLDOUT)?.map(|fs| fs.into_iter().map(PathBuf::from).collect()));
+}
+
+// perplexity of /files/ under the model in /store/
+pub fn perplexity(store: &dyn ChainStore, smoothing: smoothing::Smoothing, files: &Vec<PathBuf>) -> Result<f64, Error> {
+    let counts = load(store)?;
+    let model = smoothing::build(&counts, smoothing);
+    return Ok(evaluate::evaluate(&*model, files, &*tokenizer(store)?).perplexity());
+}
+
+// meta tree keys
+pub const HOLDOUT: &str = "holdout";
+pub const SMOOTHING: &str = "smoothing";
+pub const REVERSE: &str = "reverse";
+pub const GROUPING: &str = "grouping";
+pub const GROUP_SCOPE: &str = "group_scope";
+pub const CHAINS: &str = "chains";
+pub const SENTENCES: &str = "sentences";
+pub const UNIT: &str = "unit";
+// set, to whether classes are used, when rare words were replaced with <unk>
+pub const UNKNOWN_CLASSES: &str = "unknown_classes";
+
+// the unit of character models and of word models split into subwords, plain word models don't set one
+pub const CHAR_UNIT: &str = "char";
+pub const BPE_UNIT: &str = "bpe";
+pub const BPE_MERGES: &str = "bpe_merges";
+
+pub fn is_char_model(store: &dyn ChainStore) -> Result<bool, Error> {
+    return Ok(get_meta::<String>(store, UNIT)?.map(|u| u == CHAR_UNIT).unwrap_or(false));
+}
+
+// the subword tokenizer learned for this db with `tokenizer learn`, if there is one
+pub fn load_bpe(store: &dyn ChainStore) -> Result<Option<Bpe>, Error> {
+    if get_meta::<String>(store, UNIT)?.map(|u| u == BPE_UNIT).unwrap_or(false) {
+        return Ok(get_meta::<Vec<(String, String)>>(store, BPE_MERGES)?.map(bpe::new));
+    }
+    return Ok(None);
+}
+
+// how a db's training text was split into tokens, so it can be scored the same way. words that were
+// left out of the vocabulary come out as <unk>, as they did in training.
+pub fn tokenizer(store: &dyn ChainStore) -> Result<Box<dyn Fn(&str) -> Vec<String>>, Error> {
+    let tokenize = base_tokenizer(store)?;
+    let classes = match get_meta::<bool>(store, UNKNOWN_CLASSES)? {
+        Some(c) => c,
+        None => return Ok(tokenize),
+    };
+
+    let keep: HashSet<String> = store.groups()?.into_iter()
+        .map(|(w, _)| w)
+        .filter(|w| !vocab::is_unknown(w))
+        .collect();
+    let vocabulary = Vocabulary { keep, classes };
+    return Ok(Box::new(move |data| vocabulary.map(tokenize(data))));
+}
+
+// <unk> class -> the rare words it stands for
+pub fn load_rare(store: &dyn ChainStore) -> Result<HashMap<String, Vec<String>>, Error> {
+    return store.rare();
+}
+
+fn base_tokenizer(store: &dyn ChainStore) -> Result<Box<dyn Fn(&str) -> Vec<String>>, Error> {
+    if is_char_model(store)? {
+        return Ok(Box::new(char_corpus));
+    }
+    if let Some(b) = load_bpe(store)? {
+        return Ok(Box::new(move |data| b.tokenize(data)));
+    }
+    if get_meta::<bool>(store, SENTENCES)? == Some(true) {
+        return Ok(Box::new(sentence_corpus));
+    }
+    return Ok(Box::new(create_corpus));
+}
+
+// small pieces of information about how a db was trained are kept in the meta tree
+pub fn get_meta<T: DeserializeOwned>(store: &dyn ChainStore, key: &str) -> Result<Option<T>, Error> {
+    return match store.meta(key)? {
+        Some(v) => bincode::deserialize(&v).map(Some)
+            .map_err(|e| Error::Format(format!("couldnt decode {} in the db meta: {}", key, e))),
+        None => Ok(None),
+    };
+}
+
+pub fn set_meta<T: Serialize>(store: &dyn ChainStore, key: &str, value: &T) -> Result<(), Error> {
+    return store.set_meta(key, bincode::serialize(value)?);
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sled::Db;
+
+use crate::error::{self, Error};
+use crate::model::smoothing::{self, Smoothing};
+use crate::model::{chain_orders, get_meta, load_chains, REVERSE};
+use crate::model::store::{self, ChainStore};
+
+pub struct Pruned {
+    pub ngrams: u64,
+    pub ngrams_removed: u64,
+    pub contexts: u64,
+    pub contexts_removed: u64,
+    // bytes on disk before and after, once compacted
+    pub size: (u64, u64),
+}
+
+// Drop every successor seen fewer than /min_count/ times after its context, and contexts left with
+// none. With /entropy/, an n-gram also goes when backing off to the shorter context instead changes
+// the model little (Stolcke 1998): its share of the corpus times the log ratio of its probability
+// to the backed-off one, the context's back-off weight times the shorter context's estimate, has to
+// reach the threshold for it to stay. Reverse chains are pruned the same way.
+pub fn prune(store: &dyn ChainStore, min_count: u32, entropy: Option<(f64, Smoothing)>) -> Result<Pruned, Error> {
+    let mut pruned = Pruned {
+        ngrams: 0,
+        ngrams_removed: 0,
+        contexts: 0,
+        contexts_removed: 0,
+        size: (0, 0),
+    };
+
+    let mut directions = vec![false];
+    if get_meta::<bool>(store, REVERSE)? == Some(true) {
+        directions.push(true);
+    }
+
+    let orders = chain_orders(store)?;
+
+    for reverse in directions {
+        // work out everything to change first, the estimates are made from the chains being pruned
+        let mut changes: Vec<(Vec<String>, Option<HashMap<String, u32>>)> = Vec::new();
+        {
+            let counts = load_chains(store, reverse)?;
+            let model = entropy.map(|(_, s)| smoothing::build(&counts, s));
+            let total: u64 = counts.successors(&[]).map(|s| s.values().map(|c| *c as u64).sum()).unwrap_or(0);
+
+            for g in orders.iter() {
+                store.each_context(*g, reverse, &mut |key, value| {
+                    let weight = model.as_ref().map(|m| m.backoff_weight(&key)).unwrap_or(1.0);
+                    let kept: HashMap<String, u32> = value.iter()
+                        .filter(|(w, c)| {
+                            if **c < min_count {
+                                return false;
+                            }
+                            return match (model.as_ref(), entropy) {
+                                (Some(m), Some((threshold, _))) if !key.is_empty() => {
+                                    let p = m.arpa_prob(&key, w);
+                                    let q = weight * m.lower(&key[1..], w);
+                                    let share = **c as f64 / total.max(1) as f64;
+                                    share * (p.ln() - q.ln()) >= threshold
+                                }
+                                _ => true,
+                            };
+                        })
+                        .map(|(w, c)| (w.to_string(), *c))
+                        .collect();
+
+                    pruned.contexts += 1;
+                    pruned.ngrams += value.len() as u64;
+                    pruned.ngrams_removed += (value.len() - kept.len()) as u64;
+                    if kept.is_empty() {
+                        pruned.contexts_removed += 1;
+                        changes.push((key, None));
+                    } else if kept.len() < value.len() {
+                        changes.push((key, Some(kept)));
+                    }
+                })?;
+            }
+        }
+
+        // the key says which chain it belongs in, it's as long as the chain's order
+        for (key, value) in changes {
+            store.replace(key.len() as u32, reverse, &key, value)?;
+        }
+    }
+
+    return Ok(pruned);
+}
+
+// sled doesn't give space back when keys are removed, so copy every tree into a fresh db and swap
+// it in for the old one. /db/ has to be closed by now.
+pub fn compact(db_path: &str) -> Result<(), Error> {
+    let fresh_path = format!("{}.compact", db_path);
+    let old_path = format!("{}.old", db_path);
+
+    // left over from a run that failed part way, it'd be merged into the copy
+    if Path::new(&fresh_path).exists() {
+        fs::remove_dir_all(&fresh_path).map_err(error::io(&format!("couldnt remove {}", fresh_path)))?;
+    }
+    {
+        let db = store::reopen_db(db_path)?;
+        let fresh = Db::open(&fresh_path)?;
+        for name in db.tree_names() {
+            let from = db.open_tree(&name)?;
+            let to = fresh.open_tree(&name)?;
+            for r in from.iter() {
+                let (k, v) = r?;
+                to.insert(k, v)?;
+            }
+        }
+        fresh.flush()?;
+    }
+
+    let swapping = format!("couldnt swap the compacted db in for {}", db_path);
+    fs::rename(db_path, &old_path).map_err(error::io(&swapping))?;
+    // the old db is all there is at this point, say where it went
+    fs::rename(&fresh_path, db_path)
+        .map_err(error::io(&format!("{}, the uncompacted db is at {}", swapping, old_path)))?;
+    fs::remove_dir_all(&old_path).map_err(error::io(&format!("couldnt remove {}", old_path)))?;
+    return Ok(());
+}
+
+// bytes taken up by everything under /path/
+pub fn size(path: &Path) -> io::Result<u64> {
+    let meta = fs::metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += size(&entry?.path())?;
+    }
+    return Ok(total);
+}
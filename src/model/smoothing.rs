@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::Error;
+use crate::model::{backoff, get_meta, Counts, SMOOTHING};
+use crate::model::store::ChainStore;
+
+pub const DEFAULT_K: f64 = 1.0;
+pub const DEFAULT_KN_DISCOUNT: f64 = 0.75;
+
+// Anything that can put a probability on a word following a context.
+pub trait Estimator {
+    fn counts(&self) -> &Counts;
+
+    // P(word | context), the context can be longer than the model order
+    fn prob(&self, context: &[String], word: &str) -> f64;
+
+    // probability used for the lower orders when backing off from a longer context. Kneser-Ney
+    // swaps counts for continuation counts here, everything else is the same as prob.
+    fn lower(&self, context: &[String], word: &str) -> f64 {
+        return self.prob(context, word);
+    }
+
+    // The model written out ARPA style, as export and compile do: a seen n-gram's probability, where
+    // only the highest order is ever used directly for a full length context and shorter ones are
+    // only reached by backing off.
+    fn arpa_prob(&self, context: &[String], word: &str) -> f64 {
+        if context.len() + 1 == self.counts().order {
+            return self.prob(context, word);
+        }
+        return self.lower(context, word);
+    }
+
+    // and a stored context's back-off weight, the mass its seen successors leave over what the
+    // shorter context gives the words that weren't seen. 0 if the shorter context has none to give.
+    fn backoff_weight(&self, context: &[String]) -> f64 {
+        let successors = match self.counts().successors(context) {
+            Some(s) if !context.is_empty() => s,
+            _ => return 1.0,
+        };
+        let seen: f64 = successors.keys().map(|w| self.arpa_prob(context, w)).sum();
+        let lower: f64 = successors.keys().map(|w| self.lower(&context[1..], w)).sum();
+        if lower >= 1.0 {
+            return 0.0;
+        }
+        return (1.0 - seen).max(0.0) / (1.0 - lower);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    // absolute discounting with back-off weights
+    Backoff(f64),
+    AddK(f64),
+    WittenBell,
+    // interpolated Kneser-Ney with a fixed discount
+    KneserNey(f64),
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Smoothing::Backoff(d) => write!(f, "backoff:{}", d),
+            Smoothing::AddK(k) => write!(f, "addk:{}", k),
+            Smoothing::WittenBell => write!(f, "wittenbell"),
+            Smoothing::KneserNey(d) => write!(f, "kn:{}", d),
+        };
+    }
+}
+
+// parse name[:parameter], e.g. `kn:0.8`, `addk:0.1` or `wittenbell`
+pub fn parse(spec: &str) -> Result<Smoothing, Error> {
+    let mut parts = spec.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    let param = match parts.next() {
+        Some(p) => Some(p.trim().parse::<f64>().map_err(|e| Error::Parse(format!("bad parameter {}: {}", p, e)))?),
+        None => None,
+    };
+
+    // anything else leaves some seen words with no probability at all
+    let discount = |d: f64| -> Result<f64, Error> {
+        if d > 0.0 && d < 1.0 {
+            return Ok(d);
+        }
+        return Err(Error::Parse(format!("bad discount {} for {}, it must be between 0 and 1", d, name)));
+    };
+
+    return match name {
+        "backoff" => Ok(Smoothing::Backoff(discount(param.unwrap_or(backoff::DEFAULT_DISCOUNT))?)),
+        "addk" => match param.unwrap_or(DEFAULT_K) {
+            k if k > 0.0 => Ok(Smoothing::AddK(k)),
+            k => Err(Error::Parse(format!("bad k {} for addk, it must be above 0", k))),
+        },
+        "wittenbell" | "wb" => Ok(Smoothing::WittenBell),
+        "kn" | "kneserney" => Ok(Smoothing::KneserNey(discount(param.unwrap_or(DEFAULT_KN_DISCOUNT))?)),
+        _ => Err(Error::Parse(format!("unknown smoothing {}, expected one of backoff|addk|wittenbell|kn", name))),
+    };
+}
+
+// smoothing chosen on the command line, else the one the db was trained with, else back-off
+pub fn select(store: &dyn ChainStore, spec: Option<&str>) -> Result<Smoothing, Error> {
+    if let Some(s) = spec {
+        return parse(s);
+    }
+
+    return match get_meta::<String>(store, SMOOTHING)? {
+        Some(s) => parse(&s),
+        None => Ok(Smoothing::Backoff(backoff::DEFAULT_DISCOUNT)),
+    };
+}
+
+pub fn build(counts: &Counts, smoothing: Smoothing) -> Box<dyn Estimator + '_> {
+    return match smoothing {
+        Smoothing::Backoff(d) => Box::new(backoff::new(counts, d)),
+        Smoothing::AddK(k) => Box::new(AddK { counts, k }),
+        Smoothing::WittenBell => Box::new(WittenBell { counts, totals: totals(&counts.contexts) }),
+        Smoothing::KneserNey(d) => {
+            // N1+(• h w): how many different words were seen before each n-gram
+            let mut continuations: HashMap<Vec<String>, HashMap<String, u32>> = HashMap::new();
+            counts.contexts.iter()
+                .filter(|(k, _)| !k.is_empty())
+                .for_each(|(k, v)| {
+                    let c = continuations.entry(k[1..].to_vec()).or_insert_with(HashMap::new);
+                    v.keys().for_each(|w| *c.entry(w.to_string()).or_insert(0) += 1);
+                });
+
+            let continuation_totals = totals(&continuations)
+                .into_iter()
+                .map(|(k, v)| (k.to_vec(), v))
+                .collect();
+
+            Box::new(KneserNey {
+                counts,
+                discount: d,
+                totals: totals(&counts.contexts),
+                continuations,
+                continuation_totals,
+            })
+        }
+    };
+}
+
+// (sum of counts, number of distinct successors) for every context
+fn totals(contexts: &HashMap<Vec<String>, HashMap<String, u32>>) -> HashMap<&[String], (u32, u32)> {
+    return contexts.iter()
+        .map(|(k, v)| (k.as_slice(), (v.values().sum(), v.len() as u32)))
+        .collect();
+}
+
+// number of outcomes the base distributions are spread over, +1 for unknown words
+fn vocabulary_size(counts: &Counts) -> f64 {
+    return counts.vocabulary.len() as f64 + 1.0;
+}
+
+// Add-k on the longest context that was seen in training.
+pub struct AddK<'a> {
+    counts: &'a Counts,
+    k: f64,
+}
+
+impl<'a> Estimator for AddK<'a> {
+    fn counts(&self) -> &Counts {
+        return self.counts;
+    }
+
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        let mut context = self.counts.history(context);
+        loop {
+            if let Some(successors) = self.counts.successors(context) {
+                let total: u32 = successors.values().sum();
+                let c = *successors.get(word).unwrap_or(&0) as f64;
+                return (c + self.k) / (total as f64 + self.k * vocabulary_size(self.counts));
+            }
+            if context.is_empty() {
+                return 1.0 / vocabulary_size(self.counts);
+            }
+            context = &context[1..];
+        }
+    }
+}
+
+// Interpolated Witten-Bell: a context hands the shorter context as much weight as the number of
+// distinct words it has seen.
+pub struct WittenBell<'a> {
+    counts: &'a Counts,
+    totals: HashMap<&'a [String], (u32, u32)>,
+}
+
+impl<'a> Estimator for WittenBell<'a> {
+    fn counts(&self) -> &Counts {
+        return self.counts;
+    }
+
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        let context = self.counts.history(context);
+        let lower = if context.is_empty() {
+            1.0 / vocabulary_size(self.counts)
+        } else {
+            self.prob(&context[1..], word)
+        };
+
+        return match (self.counts.successors(context), self.totals.get(context)) {
+            (Some(successors), Some(&(total, types))) => {
+                let c = *successors.get(word).unwrap_or(&0) as f64;
+                (c + types as f64 * lower) / (total as f64 + types as f64)
+            }
+            _ => lower,
+        };
+    }
+}
+
+// Interpolated Kneser-Ney, the lower orders use continuation counts rather than raw counts.
+pub struct KneserNey<'a> {
+    counts: &'a Counts,
+    discount: f64,
+    totals: HashMap<&'a [String], (u32, u32)>,
+    continuations: HashMap<Vec<String>, HashMap<String, u32>>,
+    continuation_totals: HashMap<Vec<String>, (u32, u32)>,
+}
+
+impl<'a> KneserNey<'a> {
+    fn interpolate(&self, successors: Option<&HashMap<String, u32>>, totals: Option<&(u32, u32)>,
+                   word: &str, lower: f64) -> f64 {
+        return match (successors, totals) {
+            (Some(successors), Some(&(total, types))) if total > 0 => {
+                let c = *successors.get(word).unwrap_or(&0) as f64;
+                let total = total as f64;
+                (c - self.discount).max(0.0) / total + self.discount * types as f64 / total * lower
+            }
+            _ => lower,
+        };
+    }
+}
+
+impl<'a> Estimator for KneserNey<'a> {
+    fn counts(&self) -> &Counts {
+        return self.counts;
+    }
+
+    fn prob(&self, context: &[String], word: &str) -> f64 {
+        let context = self.counts.history(context);
+        let lower = if context.is_empty() {
+            1.0 / vocabulary_size(self.counts)
+        } else {
+            self.lower(&context[1..], word)
+        };
+
+        return self.interpolate(self.counts.successors(context), self.totals.get(context), word, lower);
+    }
+
+    fn lower(&self, context: &[String], word: &str) -> f64 {
+        let lower = if context.is_empty() {
+            1.0 / vocabulary_size(self.counts)
+        } else {
+            self.lower(&context[1..], word)
+        };
+
+        return self.interpolate(self.continuations.get(context), self.continuation_totals.get(context), word, lower);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::backoff::UNKNOWN;
+
+    // a b a c a b, counted the way model::load counts the chains: every bigram under its unigram too
+    fn counts() -> Counts {
+        let bigrams = vec![("a", vec![("b", 2), ("c", 1)]), ("b", vec![("a", 1)]), ("c", vec![("a", 1)])];
+        let mut contexts: HashMap<Vec<String>, HashMap<String, u32>> = HashMap::new();
+        for (k, successors) in bigrams {
+            for (w, c) in successors {
+                contexts.entry(vec![k.to_string()]).or_insert_with(HashMap::new).insert(w.to_string(), c);
+                *contexts.entry(Vec::new()).or_insert_with(HashMap::new).entry(w.to_string()).or_insert(0) += c;
+            }
+        }
+        let vocabulary = ["a", "b", "c"].iter().map(|w| w.to_string()).collect();
+        return Counts { contexts, vocabulary, order: 2 };
+    }
+
+    // every estimator is a distribution over the vocabulary and <unk>, after a context it has seen
+    // or not, and so is the ARPA form of it export and compile write out
+    #[test]
+    fn sums_to_one() {
+        let counts = counts();
+        let mut outcomes: Vec<String> = counts.vocabulary.iter().cloned().collect();
+        outcomes.push(UNKNOWN.to_string());
+        let close = |total: f64| (total - 1.0).abs() < 1e-9;
+
+        for s in &[Smoothing::Backoff(0.5), Smoothing::AddK(1.0), Smoothing::AddK(0.1), Smoothing::WittenBell,
+                   Smoothing::KneserNey(0.75)] {
+            let model = build(&counts, *s);
+            for context in &[vec!["a"], vec!["b"], vec!["zebra"], vec![]] {
+                let context: Vec<String> = context.iter().map(|w| w.to_string()).collect();
+                let total: f64 = outcomes.iter().map(|w| model.prob(&context, w)).sum();
+                assert!(close(total), "{} after {:?} sums to {}", s, context, total);
+            }
+
+            let context = vec!["a".to_string()];
+            let seen = counts.successors(&context).unwrap();
+            let weight = model.backoff_weight(&context);
+            let total: f64 = outcomes.iter()
+                .map(|w| if seen.contains_key(w) { model.arpa_prob(&context, w) } else { weight * model.lower(&[], w) })
+                .sum();
+            assert!(close(total), "{} written out ARPA style sums to {}", s, total);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+extern crate sled;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use sled::{Db, IVec};
+
+use crate::error::Error;
+
+// Everything a trained model is kept in: the word -> group map, the chains themselves (one per
+// order and direction, context -> successor counts), small bits of meta and the rare words <unk>
+// stands for. train writes through it and model::load reads the chains back, neither cares what's
+// underneath.
+pub trait ChainStore {
+    fn group(&self, word: &str) -> Result<Option<u32>, Error>;
+    fn set_group(&self, word: &str, group: u32) -> Result<(), Error>;
+    fn groups(&self) -> Result<Vec<(String, u32)>, Error>;
+
+    // successor counts of a context in the chain of the given order, reverse chains are separate
+    fn successors(&self, order: u32, reverse: bool, context: &[String]) -> Result<Option<HashMap<String, u32>>, Error>;
+    // count one more /word/ after /context/
+    fn increment(&self, order: u32, reverse: bool, context: &[String], word: &str) -> Result<(), Error> {
+        let mut successors = self.successors(order, reverse, context)?.unwrap_or_default();
+        *successors.entry(word.to_string()).or_insert(0) += 1;
+        return self.replace(order, reverse, context, Some(successors));
+    }
+    // swap a context's successors for new ones, None removes the context
+    fn replace(&self, order: u32, reverse: bool, context: &[String], successors: Option<HashMap<String, u32>>) -> Result<(), Error>;
+    fn each_context(&self, order: u32, reverse: bool, f: &mut dyn FnMut(Vec<String>, HashMap<String, u32>)) -> Result<(), Error>;
+    // drop the group map and every chain, meta and rare words are left as they are
+    fn clear(&self) -> Result<(), Error>;
+
+    // bincode encoded, see model::get_meta
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+    fn set_meta(&self, key: &str, value: Vec<u8>) -> Result<(), Error>;
+    fn remove_meta(&self, key: &str) -> Result<(), Error>;
+
+    // <unk> class -> the rare words it stands for
+    fn rare(&self) -> Result<HashMap<String, Vec<String>>, Error>;
+    fn set_rare(&self, class: &str, words: &Vec<String>) -> Result<(), Error>;
+    fn clear_rare(&self) -> Result<(), Error>;
+
+    fn flush(&self) -> Result<(), Error>;
+}
+
+// the on-disk store, a sled tree per chain plus `groups`, `meta` and `rare`
+pub struct Sled {
+    db: Db,
+}
+
+// an existing db, anything that only reads one shouldn't leave an empty db behind for a mistyped path
+pub fn open(db_path: &str) -> Result<Sled, Error> {
+    if !Path::new(db_path).exists() {
+        return Err(Error::Storage(format!("no db at {}", db_path)));
+    }
+    return create(db_path);
+}
+
+// a db to train into, made if there isn't one yet
+pub fn create(db_path: &str) -> Result<Sled, Error> {
+    return match Db::open(db_path) {
+        Ok(db) => Ok(Sled { db }),
+        Err(e) => Err(Error::Storage(format!("couldnt open db {} the error was: {}", db_path, e))),
+    };
+}
+
+// Open a db this process has only just closed. sled's background threads let go of its lock once
+// they've finished with it, so wait for them for a bit rather than failing straight away.
+pub fn reopen(db_path: &str) -> Result<Sled, Error> {
+    return Ok(Sled { db: reopen_db(db_path)? });
+}
+
+pub fn reopen_db(db_path: &str) -> Result<Db, Error> {
+    let mut tries = 0;
+    loop {
+        match Db::open(db_path) {
+            // sled gives a held lock as a plain io error
+            Err(sled::Error::Io(_)) if tries < 50 => {
+                tries += 1;
+                thread::sleep(Duration::from_millis(100));
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+impl ChainStore for Sled {
+    fn group(&self, word: &str) -> Result<Option<u32>, Error> {
+        return self.db.open_tree("groups")?.get(word)?.map(ivec_to_u32).transpose();
+    }
+
+    fn set_group(&self, word: &str, group: u32) -> Result<(), Error> {
+        self.db.open_tree("groups")?.insert(word, u32_to_ivec(group))?;
+        return Ok(());
+    }
+
+    fn groups(&self) -> Result<Vec<(String, u32)>, Error> {
+        return self.db.open_tree("groups")?.iter()
+            .map(|r| {
+                let (k, v) = r?;
+                return Ok((String::from_utf8_lossy(&k).to_string(), ivec_to_u32(v)?));
+            })
+            .collect();
+    }
+
+    fn successors(&self, order: u32, reverse: bool, context: &[String]) -> Result<Option<HashMap<String, u32>>, Error> {
+        let key = bincode::serialize(context)?;
+        return match self.chain(order, reverse)?.get(key)? {
+            Some(v) => Ok(Some(bincode::deserialize(&v)?)),
+            None => Ok(None),
+        };
+    }
+
+    // in place, so concurrent writers don't lose counts
+    fn increment(&self, order: u32, reverse: bool, context: &[String], word: &str) -> Result<(), Error> {
+        let key = bincode::serialize(context)?;
+        self.chain(order, reverse)?
+            .update_and_fetch(key, partial_application::partial!(add_to_chain, word.to_string(), _))?;
+        return Ok(());
+    }
+
+    fn replace(&self, order: u32, reverse: bool, context: &[String], successors: Option<HashMap<String, u32>>) -> Result<(), Error> {
+        let key = bincode::serialize(context)?;
+        let chain = self.chain(order, reverse)?;
+        match successors {
+            Some(s) => chain.insert(key, bincode::serialize(&s)?)?,
+            None => chain.remove(key)?,
+        };
+        return Ok(());
+    }
+
+    fn each_context(&self, order: u32, reverse: bool, f: &mut dyn FnMut(Vec<String>, HashMap<String, u32>)) -> Result<(), Error> {
+        for r in self.chain(order, reverse)?.iter() {
+            let (k, v) = r?;
+            f(bincode::deserialize(&k)?, bincode::deserialize(&v)?);
+        }
+        return Ok(());
+    }
+
+    fn clear(&self) -> Result<(), Error> {
+        // everything but meta, rare and sled's own default tree is groups or a chain
+        for name in self.db.tree_names() {
+            if name != b"meta" && name != RARE.as_bytes() && name != b"__sled__default" {
+                self.db.open_tree(name)?.clear()?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        return Ok(self.db.open_tree("meta")?.get(key)?.map(|v| v.to_vec()));
+    }
+
+    fn set_meta(&self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        self.db.open_tree("meta")?.insert(key, value)?;
+        return Ok(());
+    }
+
+    fn remove_meta(&self, key: &str) -> Result<(), Error> {
+        self.db.open_tree("meta")?.remove(key)?;
+        return Ok(());
+    }
+
+    fn rare(&self) -> Result<HashMap<String, Vec<String>>, Error> {
+        return self.db.open_tree(RARE)?.iter()
+            .map(|r| {
+                let (k, v) = r?;
+                return Ok((String::from_utf8_lossy(&k).to_string(), bincode::deserialize(&v)?));
+            })
+            .collect();
+    }
+
+    fn set_rare(&self, class: &str, words: &Vec<String>) -> Result<(), Error> {
+        self.db.open_tree(RARE)?.insert(class, bincode::serialize(words)?)?;
+        return Ok(());
+    }
+
+    fn clear_rare(&self) -> Result<(), Error> {
+        self.db.open_tree(RARE)?.clear()?;
+        return Ok(());
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.db.flush()?;
+        return Ok(());
+    }
+}
+
+impl Sled {
+    fn chain(&self, order: u32, reverse: bool) -> Result<sled::Tree, Error> {
+        return Ok(self.db.open_tree(chain_name(order, reverse))?);
+    }
+}
+
+// tree of <unk> class -> rare words it replaced
+const RARE: &str = "rare";
+
+// chains are named after their order, reverse chains get an `r` in front
+fn chain_name(order: u32, reverse: bool) -> IVec {
+    if reverse {
+        let mut name = b"r".to_vec();
+        name.extend_from_slice(&order.to_be_bytes());
+        return IVec::from(name);
+    }
+    return u32_to_ivec(order);
+}
+
+// chain values are successor -> number of times it followed the key, the counts are what the
+// language model estimates (see model::load) are built from. sled can't hand an error back from
+// here, so a value that doesn't decode is left as it is for whatever reads it next to report.
+fn add_to_chain(word: String, old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let mut map: HashMap<String, u32> = match old.map(bincode::deserialize) {
+        Some(Ok(m)) => m,
+        Some(Err(_)) => return old.map(|b| b.to_vec()),
+        None => HashMap::new(),
+    };
+    *map.entry(word).or_insert(0) += 1;
+
+    // serialise
+    return bincode::serialize(&map).ok();
+}
+
+fn u32_to_ivec(x: u32) -> IVec {
+    IVec::from(x.to_be_bytes().to_vec())
+}
+
+fn ivec_to_u32(x: IVec) -> Result<u32, Error> {
+    let a: [u8; 4] = x.to_vec().as_slice().try_into()
+        .map_err(|_| Error::Format(format!("group should be 4 bytes, got {}", x.len())))?;
+    return Ok(u32::from_be_bytes(a));
+}
+
+// Kept in memory only, for tests and small experiments that don't need a db on disk. Gone when
+// dropped.
+pub struct Memory {
+    groups: RefCell<HashMap<String, u32>>,
+    chains: RefCell<HashMap<(u32, bool), HashMap<Vec<String>, HashMap<String, u32>>>>,
+    meta: RefCell<HashMap<String, Vec<u8>>>,
+    rare: RefCell<HashMap<String, Vec<String>>>,
+}
+
+pub fn memory() -> Memory {
+    return Memory {
+        groups: RefCell::new(HashMap::new()),
+        chains: RefCell::new(HashMap::new()),
+        meta: RefCell::new(HashMap::new()),
+        rare: RefCell::new(HashMap::new()),
+    };
+}
+
+impl ChainStore for Memory {
+    fn group(&self, word: &str) -> Result<Option<u32>, Error> {
+        return Ok(self.groups.borrow().get(word).cloned());
+    }
+
+    fn set_group(&self, word: &str, group: u32) -> Result<(), Error> {
+        self.groups.borrow_mut().insert(word.to_string(), group);
+        return Ok(());
+    }
+
+    fn groups(&self) -> Result<Vec<(String, u32)>, Error> {
+        return Ok(self.groups.borrow().iter().map(|(w, g)| (w.to_string(), *g)).collect());
+    }
+
+    fn successors(&self, order: u32, reverse: bool, context: &[String]) -> Result<Option<HashMap<String, u32>>, Error> {
+        return Ok(self.chains.borrow().get(&(order, reverse)).and_then(|c| c.get(context).cloned()));
+    }
+
+    // counted in place, the default would copy the whole successor map for every word
+    fn increment(&self, order: u32, reverse: bool, context: &[String], word: &str) -> Result<(), Error> {
+        let mut chains = self.chains.borrow_mut();
+        let successors = chains.entry((order, reverse)).or_insert_with(HashMap::new)
+            .entry(context.to_vec()).or_insert_with(HashMap::new);
+        *successors.entry(word.to_string()).or_insert(0) += 1;
+        return Ok(());
+    }
+
+    fn replace(&self, order: u32, reverse: bool, context: &[String], successors: Option<HashMap<String, u32>>) -> Result<(), Error> {
+        let mut chains = self.chains.borrow_mut();
+        let chain = chains.entry((order, reverse)).or_insert_with(HashMap::new);
+        match successors {
+            Some(s) => chain.insert(context.to_vec(), s),
+            None => chain.remove(context),
+        };
+        return Ok(());
+    }
+
+    fn each_context(&self, order: u32, reverse: bool, f: &mut dyn FnMut(Vec<String>, HashMap<String, u32>)) -> Result<(), Error> {
+        // copied out first so /f/ is free to change the store
+        let contexts: Vec<(Vec<String>, HashMap<String, u32>)> = match self.chains.borrow().get(&(order, reverse)) {
+            Some(c) => c.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => return Ok(()),
+        };
+        contexts.into_iter().for_each(|(k, v)| f(k, v));
+        return Ok(());
+    }
+
+    fn clear(&self) -> Result<(), Error> {
+        self.groups.borrow_mut().clear();
+        self.chains.borrow_mut().clear();
+        return Ok(());
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        return Ok(self.meta.borrow().get(key).cloned());
+    }
+
+    fn set_meta(&self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        self.meta.borrow_mut().insert(key.to_string(), value);
+        return Ok(());
+    }
+
+    fn remove_meta(&self, key: &str) -> Result<(), Error> {
+        self.meta.borrow_mut().remove(key);
+        return Ok(());
+    }
+
+    fn rare(&self) -> Result<HashMap<String, Vec<String>>, Error> {
+        return Ok(self.rare.borrow().clone());
+    }
+
+    fn set_rare(&self, class: &str, words: &Vec<String>) -> Result<(), Error> {
+        self.rare.borrow_mut().insert(class.to_string(), words.clone());
+        return Ok(());
+    }
+
+    fn clear_rare(&self) -> Result<(), Error> {
+        self.rare.borrow_mut().clear();
+        return Ok(());
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        return Ok(());
+    }
+}
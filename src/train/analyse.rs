@@ -1,5 +1,8 @@
-use std::collections::{HashMap, HashSet};
-use std::fmt::Display;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::train::data::each_file;
 
 fn clean_word(w: &str) -> String {
     return w.to_ascii_lowercase()
@@ -9,10 +12,14 @@ fn clean_word(w: &str) -> String {
         .to_string();
 }
 
+// handle no whitespace after punctuation
+fn is_separator(c: char) -> bool {
+    return c.is_whitespace() || c == '.' || c == '⏺';
+}
+
 pub fn create_corpus(corpus: &str) -> Vec<String> {
     return corpus
-        // handle no whitespace after punctuation
-        .split(|c: char| c.is_whitespace() || c == '.' || c == '⏺')
+        .split(is_separator)
         // clean up words
         .map(clean_word)
         // remove empty items
@@ -20,80 +27,394 @@ pub fn create_corpus(corpus: &str) -> Vec<String> {
         .collect();
 }
 
-fn word_frequency(data: &str) -> HashMap<String, u64> {
-    let mut map = HashMap::new();
-    let words: Vec<String> = create_corpus(data);
+// Markers training puts around sentences and at the end of each document so the model learns how
+// sentences start and where sentences and documents end. clean_word strips < and >, so no word can
+// look like one of them.
+pub const SENTENCE_START: &str = "<s>";
+pub const SENTENCE_END: &str = "</s>";
+pub const DOCUMENT_END: &str = "</d>";
+
+pub fn is_sentinel(w: &str) -> bool {
+    return w == SENTENCE_START || w == SENTENCE_END || w == DOCUMENT_END;
+}
+
+// for character models: every word spelled out a character at a time, wrapped in <s> .. </s>
+pub fn char_corpus(corpus: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    for word in create_corpus(corpus) {
+        result.push(SENTENCE_START.to_string());
+        result.extend(word.chars().map(|c| c.to_string()));
+        result.push(SENTENCE_END.to_string());
+    }
+    result.push(DOCUMENT_END.to_string());
+
+    return result;
+}
 
-    for word in words.into_iter() {
-        if map.contains_key(&word) {
-            *map.get_mut(&word).unwrap() += 1;
-        } else {
-            map.insert(word.to_string(), 1);
+// the words of create_corpus with each sentence wrapped in <s> .. </s> and </d> at the very end
+pub fn sentence_corpus(corpus: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    for sentence in sentences(corpus) {
+        let words = create_corpus(sentence);
+        if words.is_empty() {
+            continue;
         }
+
+        result.push(SENTENCE_START.to_string());
+        result.extend(words);
+        result.push(SENTENCE_END.to_string());
     }
+    result.push(DOCUMENT_END.to_string());
 
-    return map;
+    return result;
 }
 
-fn group_frequencies(freq: &HashMap<String, u64>) -> HashMap<u64, u64> {
-    let mut map: HashMap<u64, u64> = HashMap::new();
-    freq.iter().for_each(|(_, v)| *map.entry(*v).or_insert(0) += 1);
+// split on sentence ending punctuation and on paragraph breaks
+pub fn sentences(data: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
 
-    return map;
+    let chars: Vec<(usize, char)> = data.char_indices().collect();
+    for (i, &(at, c)) in chars.iter().enumerate() {
+        let end = match c {
+            '.' | '!' | '?' => match chars.get(i + 1) {
+                // ... and ?! belong to the same sentence, as does a decimal point
+                Some((_, n)) => n.is_whitespace() || *n == '"' || *n == '”' || *n == '⏺',
+                None => true,
+            },
+            '\n' => chars.get(i + 1).map(|(_, n)| *n == '\n').unwrap_or(false),
+            '⏺' => true,
+            _ => false,
+        };
+
+        if end {
+            result.push(&data[start..at + c.len_utf8()]);
+            start = at + c.len_utf8();
+        }
+    }
+    if start < data.len() {
+        result.push(&data[start..]);
+    }
+
+    return result;
 }
 
-pub fn print_kv<K: Display, V: Display>(mut set: Vec<(K, V)>, first: usize, last: usize) -> () {
-    if first == 0 && last == 0 {
-        set.iter().for_each(|(k, v)| println!("{} {}", k, v))
-    } else {
-        if first != 0 {
-            set.iter()
-                .take(first)
-                .for_each(|(k, v)| println!("[{}] {}", k, v));
-            println!();
+// Same words as create_corpus, along with the byte range each one was taken from in the original
+// text (without the punctuation clean_word trims off).
+pub fn tokenize(corpus: &str) -> Vec<(usize, usize, String)> {
+    let mut result = Vec::new();
+
+    let mut start = 0;
+    for (i, c) in corpus.char_indices().chain(std::iter::once((corpus.len(), ' '))) {
+        if !is_separator(c) {
+            continue;
         }
-        if last != 0 {
-            set.reverse();
-            set.iter()
-                .take(first)
-                .for_each(|(k, v)| println!("[{}] {}", k, v));
-            println!();
+
+        let segment = &corpus[start..i];
+        let w = clean_word(segment);
+        if w.len() > 1 || w == "i" {
+            let s = segment.find(|c: char| c.is_alphabetic()).unwrap_or(0);
+            let e = segment.rfind(|c: char| c.is_alphabetic())
+                .map(|e| e + segment[e..].chars().next().unwrap().len_utf8())
+                .unwrap_or(segment.len());
+            result.push((start + s, start + e, w));
         }
+
+        start = i + c.len_utf8();
     }
+
+    return result;
 }
 
-pub fn dump_cmd(data: &str) -> () {
-    let words = create_corpus(data);
+pub fn word_frequency(files: &Vec<PathBuf>) -> HashMap<String, u64> {
+    let mut map = HashMap::new();
 
-    let mut set: HashSet<String> = HashSet::new();
-    words.into_iter().for_each(|w| {
-        set.insert(w);
-        return ();
+    each_file(files, |_, data| {
+        for word in create_corpus(data).into_iter() {
+            if map.contains_key(&word) {
+                *map.get_mut(&word).unwrap() += 1;
+            } else {
+                map.insert(word.to_string(), 1);
+            }
+        }
     });
 
-    set.into_iter().for_each(|e| println!("[{}]", e))
+    return map;
 }
 
-pub fn word_cmd(data: &str) -> Vec<(String, u64)> {
-    let freq = word_frequency(data);
+fn group_frequencies(freq: &HashMap<String, u64>) -> HashMap<u64, u64> {
+    let mut map: HashMap<u64, u64> = HashMap::new();
+    freq.iter().for_each(|(_, v)| *map.entry(*v).or_insert(0) += 1);
+
+    return map;
+}
+
+// every unique word with its count, alphabetically
+pub fn dump_cmd(files: &Vec<PathBuf>) -> Vec<(String, u64)> {
+    let mut result: Vec<(String, u64)> = word_frequency(files).into_iter().collect();
+    result.sort();
+    return result;
+}
+
+pub fn word_cmd(files: &Vec<PathBuf>) -> Vec<(String, u64)> {
+    let freq = word_frequency(files);
 
     // scan map
     let mut result: Vec<(String, u64)> = Vec::new();
     freq.into_iter()
         .for_each(|e| result.push(e));
 
-    result.sort_by(|(_, v1), (_, v2)| v2.cmp(v1));
+    result.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
     return result;
 }
 
-pub fn group_cmd(data: &str) -> Vec<(u64, u64)> {
-    let freq: HashMap<String, u64> = word_frequency(data);
+pub fn group_cmd(files: &Vec<PathBuf>) -> Vec<(u64, u64)> {
+    let freq: HashMap<String, u64> = word_frequency(files);
     let groups: HashMap<u64, u64> = group_frequencies(&freq);
 
     let mut result: Vec<(u64, u64)> = Vec::new();
     groups.into_iter()
         .for_each(|e| result.push(e)); //TODO result::push
 
-    result.sort_by(|(_, v1), (_, v2)| v2.cmp(v1));
+    result.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
+    return result;
+}
+
+// count every n-gram of length min..=max, n-grams don't cross document boundaries
+pub fn ngram_cmd(files: &Vec<PathBuf>, min: usize, max: usize) -> Vec<(usize, Vec<(String, u64)>)> {
+    let mut maps: Vec<HashMap<String, u64>> = vec![HashMap::new(); max + 1];
+
+    each_file(files, |_, data| {
+        let words = create_corpus(data);
+        for n in min..=max {
+            words.windows(n).for_each(|w| *maps[n].entry(w.join(" ")).or_insert(0) += 1);
+        }
+    });
+
+    return maps.into_iter()
+        .enumerate()
+        .skip(min)
+        .map(|(n, m)| {
+            let mut result: Vec<(String, u64)> = m.into_iter().collect();
+            result.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
+            return (n, result);
+        })
+        .collect();
+}
+
+// parse `2..5`, `2..=5` or a single `3`
+pub fn parse_range(range: &str) -> Result<(usize, usize), Error> {
+    let parse = |v: &str| v.trim().parse::<usize>().map_err(|e| Error::Parse(format!("bad range {}: {}", range, e)));
+
+    let (min, max) = if let Some(i) = range.find("..") {
+        let max = range[i + 2..].trim_start_matches('=');
+        (parse(&range[..i])?, parse(max)?)
+    } else {
+        let n = parse(range)?;
+        (n, n)
+    };
+
+    if min == 0 || max < min {
+        return Err(Error::Parse(format!("bad range {}, expected something like 2..5", range)));
+    }
+    return Ok((min, max));
+}
+
+pub struct Collocation {
+    pub count: u64,
+    pub pmi: f64,
+    pub llr: f64,
+    pub t: f64,
+}
+
+// Score word pairs that occur within /window/ words of each other by pointwise mutual information,
+// Dunning's log-likelihood ratio and t-score. Pairs seen fewer than /min_count/ times are dropped,
+// PMI in particular is meaningless for them. Sorted by /rank/, one of pmi|llr|t.
+pub fn collocation_cmd(files: &Vec<PathBuf>, window: usize, min_count: u64, rank: &str) -> Vec<(String, Collocation)> {
+    let mut pairs: HashMap<(String, String), u64> = HashMap::new();
+    let mut firsts: HashMap<String, u64> = HashMap::new();
+    let mut seconds: HashMap<String, u64> = HashMap::new();
+    let mut n: u64 = 0;
+
+    each_file(files, |_, data| {
+        let words = create_corpus(data);
+        for (i, w1) in words.iter().enumerate() {
+            for w2 in words.iter().skip(i + 1).take(window) {
+                *pairs.entry((w1.to_string(), w2.to_string())).or_insert(0) += 1;
+                *firsts.entry(w1.to_string()).or_insert(0) += 1;
+                *seconds.entry(w2.to_string()).or_insert(0) += 1;
+                n += 1;
+            }
+        }
+    });
+
+    let n = n as f64;
+    let mut result: Vec<(String, Collocation)> = pairs.into_iter()
+        .filter(|(_, c)| *c >= min_count)
+        .map(|((w1, w2), c)| {
+            let c12 = c as f64;
+            let c1 = *firsts.get(&w1).unwrap() as f64;
+            let c2 = *seconds.get(&w2).unwrap() as f64;
+            let expected = c1 * c2 / n;
+
+            // 2x2 contingency table of (w1, not w1) x (w2, not w2)
+            let observed = [c12, c1 - c12, c2 - c12, n - c1 - c2 + c12];
+            let expected_cells = [c1 * c2 / n, c1 * (n - c2) / n, (n - c1) * c2 / n, (n - c1) * (n - c2) / n];
+            let llr = 2.0 * observed.iter().zip(expected_cells.iter())
+                .filter(|(o, _)| **o > 0.0)
+                .map(|(o, e)| o * (o / e).ln())
+                .sum::<f64>();
+
+            let collocation = Collocation {
+                count: c,
+                pmi: (c12 / expected).log2(),
+                llr,
+                t: (c12 - expected) / c12.sqrt(),
+            };
+            return (format!("{} {}", w1, w2), collocation);
+        })
+        .collect();
+
+    let score = |c: &Collocation| -> f64 {
+        match rank {
+            "pmi" => c.pmi,
+            "t" => c.t,
+            _ => c.llr,
+        }
+    };
+    result.sort_by(|(k1, c1), (k2, c2)| score(c2).partial_cmp(&score(c1)).unwrap().then(k1.cmp(k2)));
+    return result;
+}
+
+pub struct Concordance {
+    pub file: PathBuf,
+    // byte offset of the match in the file
+    pub offset: usize,
+    pub left: String,
+    pub keyword: String,
+    pub right: String,
+}
+
+// Keyword in context: every place the phrase occurs, tokenized the same way as for training, with up
+// to /width/ characters of the original text either side.
+pub fn concordance_cmd(files: &Vec<PathBuf>, phrase: &str, width: usize) -> Vec<Concordance> {
+    let needle = create_corpus(phrase);
+    let mut result = Vec::new();
+    if needle.is_empty() {
+        return result;
+    }
+
+    each_file(files, |file, data| {
+        let tokens = tokenize(data);
+        for (i, window) in tokens.windows(needle.len()).enumerate() {
+            if !window.iter().zip(needle.iter()).all(|((_, _, w), n)| w == n) {
+                continue;
+            }
+
+            let start = tokens[i].0;
+            let end = tokens[i + needle.len() - 1].1;
+
+            // walk back from the match rather than over everything before it
+            let from = data[..start].char_indices().rev().take(width).last().map(|(i, _)| i).unwrap_or(start);
+            let left = &data[from..start];
+            let right: String = data[end..].chars().take(width).collect();
+
+            result.push(Concordance {
+                file: file.to_path_buf(),
+                offset: start,
+                left: flatten(left),
+                keyword: flatten(&data[start..end]),
+                right: flatten(&right),
+            });
+        }
+    });
+
     return result;
 }
+
+// keep a concordance on a single line
+fn flatten(s: &str) -> String {
+    return s.chars()
+        .map(|c| if c.is_whitespace() || c == '⏺' { ' ' } else { c })
+        .collect();
+}
+
+pub struct Keyness {
+    pub item: String,
+    pub n: usize,
+    pub a: u64,
+    pub b: u64,
+    // log-likelihood keyness, how unlikely the difference is under a single shared distribution
+    pub ll: f64,
+    // % difference of the normalised frequency in a over b, infinite for items missing from b
+    pub pct_diff: f64,
+}
+
+// Words (n = 1) and n-grams that are significantly more common in one corpus than the other. Items
+// below /min_count/ in both corpora or with a log-likelihood under /min_ll/ (3.84 is p < 0.05) are
+// dropped. Sorted by log-likelihood.
+pub fn keyness_cmd(a: &Vec<PathBuf>, b: &Vec<PathBuf>, min: usize, max: usize, min_count: u64,
+                   min_ll: f64) -> Vec<Keyness> {
+    let mut result = Vec::new();
+
+    for n in min..=max {
+        let fa = frequencies(a, n);
+        let fb = frequencies(b, n);
+        let ta = fa.values().sum::<u64>() as f64;
+        let tb = fb.values().sum::<u64>() as f64;
+
+        let mut items: Vec<&String> = fa.keys().chain(fb.keys()).collect();
+        items.sort();
+        items.dedup();
+
+        for item in items {
+            let ca = *fa.get(item).unwrap_or(&0);
+            let cb = *fb.get(item).unwrap_or(&0);
+            if ca < min_count && cb < min_count {
+                continue;
+            }
+
+            let ll = log_likelihood(ca as f64, cb as f64, ta, tb);
+            if ll < min_ll {
+                continue;
+            }
+
+            let (na, nb) = (ca as f64 / ta, cb as f64 / tb);
+            result.push(Keyness {
+                item: item.to_string(),
+                n,
+                a: ca,
+                b: cb,
+                ll,
+                pct_diff: if nb == 0.0 { std::f64::INFINITY } else { (na - nb) * 100.0 / nb },
+            });
+        }
+    }
+
+    result.sort_by(|k1, k2| k2.ll.partial_cmp(&k1.ll).unwrap().then(k1.item.cmp(&k2.item)));
+    return result;
+}
+
+fn frequencies(files: &Vec<PathBuf>, n: usize) -> HashMap<String, u64> {
+    if n == 1 {
+        return word_frequency(files);
+    }
+    return ngram_cmd(files, n, n).into_iter()
+        .flat_map(|(_, v)| v.into_iter())
+        .collect();
+}
+
+// Rayson & Garside's log-likelihood for an item seen a times in a corpus of ta and b times in tb
+fn log_likelihood(a: f64, b: f64, ta: f64, tb: f64) -> f64 {
+    let ea = ta * (a + b) / (ta + tb);
+    let eb = tb * (a + b) / (ta + tb);
+
+    let mut ll = 0.0;
+    if a > 0.0 {
+        ll += a * (a / ea).ln();
+    }
+    if b > 0.0 {
+        ll += b * (b / eb).ln();
+    }
+    return 2.0 * ll;
+}
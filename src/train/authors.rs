@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::train::analyse::create_corpus;
+use crate::train::data::each_file;
+
+// Burrows' original study used the 150 most frequent words
+pub const DEFAULT_FEATURES: usize = 150;
+// profiles from less text than this are mostly noise
+pub const DEFAULT_MIN_WORDS: u64 = 1000;
+
+pub struct Profile {
+    pub name: String,
+    pub files: usize,
+    pub words: u64,
+    counts: HashMap<String, u64>,
+    // z-scores of the relative frequency of each feature word, in feature order
+    pub z: Vec<f64>,
+}
+
+impl Profile {
+    // per 1000 words
+    pub fn rate(&self, word: &str) -> f64 {
+        if self.words == 0 {
+            return 0.0;
+        }
+        return *self.counts.get(word).unwrap_or(&0) as f64 * 1000.0 / self.words as f64;
+    }
+}
+
+pub struct Distance {
+    pub a: String,
+    pub b: String,
+    // Burrows' Delta, mean absolute difference of z-scores
+    pub delta: f64,
+    // cosine distance between the z-score vectors
+    pub cosine: f64,
+}
+
+pub struct Authors {
+    // the most frequent words over all authors, mostly function words
+    pub features: Vec<String>,
+    pub profiles: Vec<Profile>,
+    pub unknown: Option<Profile>,
+    // closest first
+    pub distances: Vec<Distance>,
+}
+
+// Stylometric distances between authors, using the relative frequencies of the /features/ most
+// frequent words standardised across authors. With an unknown text only its distance to each author
+// is given, otherwise every pair of authors. Authors with fewer than /min_words/ words are dropped.
+pub fn authors_cmd(files: &Vec<PathBuf>, by: &HashMap<PathBuf, String>, unknown: Option<(&str, &Vec<PathBuf>)>,
+                   features: usize, min_words: u64) -> Result<Authors, Error> {
+    let mut grouped: BTreeMap<&String, Vec<PathBuf>> = BTreeMap::new();
+    files.iter()
+        .filter_map(|f| by.get(f).map(|a| (a, f)))
+        .for_each(|(a, f)| grouped.entry(a).or_insert_with(Vec::new).push(f.to_path_buf()));
+
+    let mut profiles: Vec<Profile> = Vec::new();
+    for (name, files) in grouped {
+        let p = profile(name, &files);
+        if p.words < min_words {
+            eprintln!("skipping {}, only {} words", name, p.words);
+            continue;
+        }
+        profiles.push(p);
+    }
+    if profiles.len() < 2 {
+        return Err(Error::Parse(format!("need at least two authors with {} or more words, found {}", min_words,
+                                        profiles.len())));
+    }
+
+    let mut total: HashMap<&String, u64> = HashMap::new();
+    profiles.iter()
+        .flat_map(|p| p.counts.iter())
+        .for_each(|(w, c)| *total.entry(w).or_insert(0) += c);
+    let mut ranked: Vec<(&String, u64)> = total.into_iter().collect();
+    ranked.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
+    let features: Vec<String> = ranked.into_iter().take(features).map(|(w, _)| w.to_string()).collect();
+
+    // mean and sample standard deviation of each feature across authors
+    let n = profiles.len() as f64;
+    let moments: Vec<(f64, f64)> = features.iter()
+        .map(|w| {
+            let rates: Vec<f64> = profiles.iter().map(|p| p.rate(w)).collect();
+            let mean = rates.iter().sum::<f64>() / n;
+            let var = rates.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            return (mean, var.sqrt());
+        })
+        .collect();
+
+    let standardise = |p: &mut Profile| {
+        p.z = features.iter().zip(moments.iter())
+            .map(|(w, (mean, sd))| if *sd == 0.0 { 0.0 } else { (p.rate(w) - mean) / sd })
+            .collect();
+    };
+    profiles.iter_mut().for_each(|p| standardise(p));
+
+    let unknown = unknown.map(|(name, files)| {
+        let mut p = profile(name, files);
+        standardise(&mut p);
+        return p;
+    });
+
+    let mut distances = Vec::new();
+    match unknown.as_ref() {
+        Some(u) => profiles.iter().for_each(|p| distances.push(distance(u, p))),
+        None => {
+            for (i, a) in profiles.iter().enumerate() {
+                profiles.iter().skip(i + 1).for_each(|b| distances.push(distance(a, b)));
+            }
+        }
+    }
+    distances.sort_by(|d1, d2| d1.delta.partial_cmp(&d2.delta).unwrap()
+        .then(d1.a.cmp(&d2.a))
+        .then(d1.b.cmp(&d2.b)));
+
+    return Ok(Authors {
+        features,
+        profiles,
+        unknown,
+        distances,
+    });
+}
+
+fn profile(name: &str, files: &Vec<PathBuf>) -> Profile {
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let mut words: u64 = 0;
+
+    each_file(files, |_, data| {
+        for w in create_corpus(data) {
+            words += 1;
+            *counts.entry(w).or_insert(0) += 1;
+        }
+    });
+
+    return Profile {
+        name: name.to_string(),
+        files: files.len(),
+        words,
+        counts,
+        z: Vec::new(),
+    };
+}
+
+fn distance(a: &Profile, b: &Profile) -> Distance {
+    let n = a.z.len() as f64;
+    let delta = a.z.iter().zip(b.z.iter()).map(|(x, y)| (x - y).abs()).sum::<f64>() / n.max(1.0);
+
+    let dot: f64 = a.z.iter().zip(b.z.iter()).map(|(x, y)| x * y).sum();
+    let norm = |z: &Vec<f64>| z.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let cosine = if norm(&a.z) == 0.0 || norm(&b.z) == 0.0 { 1.0 } else { 1.0 - dot / (norm(&a.z) * norm(&b.z)) };
+
+    return Distance {
+        a: a.name.to_string(),
+        b: b.name.to_string(),
+        delta,
+        cosine,
+    };
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::train::analyse::{create_corpus, sentences, DOCUMENT_END, SENTENCE_END, SENTENCE_START};
+use crate::train::data::each_file;
+
+pub const DEFAULT_MERGES: usize = 1000;
+
+// marks the last piece of a word, so pieces can be joined back into words
+pub const WORD_END: &str = "</w>";
+
+// Byte-pair encoding (Sennrich et al.): words start out as characters and the most frequent adjacent
+// pair of pieces is merged, over and over. Common words end up as a single piece while rare ones,
+// invented names and misspellings are split into pieces the model has seen elsewhere.
+pub struct Bpe {
+    pub merges: Vec<(String, String)>,
+
+    // merge -> the order it was learned in, earlier merges are applied first
+    ranks: HashMap<(String, String), usize>,
+}
+
+pub fn new(merges: Vec<(String, String)>) -> Bpe {
+    let ranks = merges.iter().cloned().enumerate().map(|(i, m)| (m, i)).collect();
+    return Bpe {
+        merges,
+        ranks,
+    };
+}
+
+// learn up to /count/ merges from the words in files
+pub fn learn(files: &Vec<PathBuf>, count: usize) -> Bpe {
+    let mut freq: HashMap<String, u64> = HashMap::new();
+    each_file(files, |_, data| {
+        create_corpus(data).into_iter().for_each(|w| *freq.entry(w).or_insert(0) += 1);
+    });
+
+    let mut words: Vec<(Vec<String>, u64)> = freq.into_iter().map(|(w, f)| (characters(&w), f)).collect();
+    let mut merges = Vec::with_capacity(count);
+
+    while merges.len() < count {
+        let mut pairs: HashMap<(&String, &String), u64> = HashMap::new();
+        for (pieces, f) in words.iter() {
+            pieces.windows(2).for_each(|p| *pairs.entry((&p[0], &p[1])).or_insert(0) += f);
+        }
+
+        // most frequent first, ties broken alphabetically so the same corpus learns the same merges
+        let best = pairs.into_iter()
+            .max_by(|(p1, f1), (p2, f2)| f1.cmp(f2).then(p2.cmp(p1)))
+            .map(|((a, b), _)| (a.to_string(), b.to_string()));
+        let (a, b) = match best {
+            Some(p) => p,
+            // every word is a single piece already
+            None => break,
+        };
+
+        words.iter_mut().for_each(|(pieces, _)| merge(pieces, &a, &b));
+        merges.push((a, b));
+    }
+
+    return new(merges);
+}
+
+impl Bpe {
+    // the pieces of a single word, the last one ending in </w>
+    pub fn encode(&self, word: &str) -> Vec<String> {
+        let mut pieces = characters(word);
+        loop {
+            let best = pieces.windows(2)
+                .filter_map(|p| self.ranks.get(&(p[0].to_string(), p[1].to_string())).map(|r| (r, p)))
+                .min_by_key(|(r, _)| *r)
+                .map(|(_, p)| (p[0].to_string(), p[1].to_string()));
+
+            match best {
+                Some((a, b)) => merge(&mut pieces, &a, &b),
+                None => return pieces,
+            }
+        }
+    }
+
+    pub fn encode_words(&self, words: &Vec<String>) -> Vec<String> {
+        return words.iter().flat_map(|w| self.encode(w)).collect();
+    }
+
+    // like analyse::sentence_corpus, with every word split into pieces
+    pub fn tokenize(&self, corpus: &str) -> Vec<String> {
+        let mut cache: HashMap<String, Vec<String>> = HashMap::new();
+
+        let mut result = Vec::new();
+        for sentence in sentences(corpus) {
+            let words = create_corpus(sentence);
+            if words.is_empty() {
+                continue;
+            }
+
+            result.push(SENTENCE_START.to_string());
+            for w in words {
+                let pieces = cache.entry(w.clone()).or_insert_with(|| self.encode(&w));
+                result.extend(pieces.iter().cloned());
+            }
+            result.push(SENTENCE_END.to_string());
+        }
+        result.push(DOCUMENT_END.to_string());
+
+        return result;
+    }
+}
+
+// join pieces back up into words
+pub fn detokenize(pieces: &[String]) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for p in pieces {
+        if p.ends_with(WORD_END) {
+            word.push_str(&p[..p.len() - WORD_END.len()]);
+            words.push(word);
+            word = String::new();
+        } else {
+            word.push_str(p);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    return words;
+}
+
+fn characters(word: &str) -> Vec<String> {
+    let mut pieces: Vec<String> = word.chars().map(|c| c.to_string()).collect();
+    if let Some(last) = pieces.last_mut() {
+        last.push_str(WORD_END);
+    }
+    return pieces;
+}
+
+// replace every adjacent a, b with ab
+fn merge(pieces: &mut Vec<String>, a: &str, b: &str) -> () {
+    let mut i = 0;
+    while i + 1 < pieces.len() {
+        if pieces[i] == a && pieces[i + 1] == b {
+            let joined = format!("{}{}", a, b);
+            pieces[i] = joined;
+            pieces.remove(i + 1);
+        }
+        i += 1;
+    }
+}
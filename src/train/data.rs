@@ -1,9 +1,18 @@
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::path::{PathBuf, Path};
 
+use crate::crawl::store::INDEX;
+
+// a named set of documents, one per input given on the command line
+pub struct Source {
+    pub name: String,
+    pub files: Vec<PathBuf>,
+}
+
 pub fn read_file<P: AsRef<Path>>(file: P) -> io::Result<String> {
     let mut s = String::new();
     File::open(file)?.read_to_string(&mut s)?;
@@ -11,9 +20,193 @@ pub fn read_file<P: AsRef<Path>>(file: P) -> io::Result<String> {
 }
 
 pub fn list_dir(path: &str) -> io::Result<Vec<PathBuf>> {
-    return Ok(fs::read_dir(path)?.into_iter()
-        .map(|p| p.unwrap().path())
-        .collect());
+    return fs::read_dir(path)?.into_iter()
+        .map(|p| p.map(|e| e.path()))
+        .collect();
+}
+
+// Expand every input into a source: a file is used as is, a directory (such as a crawler store) is
+// walked recursively and anything with * or ? in it is matched as a glob. Hidden files are skipped,
+// files come out sorted so runs are repeatable.
+pub fn sources(inputs: &Vec<&str>) -> io::Result<Vec<Source>> {
+    let mut result = Vec::new();
+    for input in inputs {
+        let mut files = if input.contains(|c| c == '*' || c == '?') {
+            glob(input)?
+        } else {
+            walk(Path::new(input))?
+        };
+        files.sort();
+
+        result.push(Source {
+            name: input.to_string(),
+            files,
+        });
+    }
+
+    return Ok(result);
+}
+
+// all files under path, or the path itself if it is a file
+pub fn walk(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        // surface missing files here rather than when reading them
+        fs::metadata(path)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let p = entry?.path();
+        if hidden(&p) {
+            continue;
+        }
+        files.extend(walk(&p)?);
+    }
+    return Ok(files);
+}
+
+// match a pattern one path component at a time, `**` matches any number of directories
+fn glob(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let root = if pattern.starts_with('/') { PathBuf::from("/") } else { PathBuf::from(".") };
+    let parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty() && *p != ".").collect();
+
+    let mut files = Vec::new();
+    glob_from(&root, &parts, &mut files)?;
+    return Ok(files);
+}
+
+fn glob_from(dir: &Path, parts: &[&str], files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let (part, rest) = match parts.split_first() {
+        Some(p) => p,
+        None => {
+            if dir.is_file() {
+                files.push(dir.to_path_buf());
+            }
+            return Ok(());
+        }
+    };
+
+    if *part == "**" {
+        // zero directories, or one more and keep the ** around
+        glob_from(dir, rest, files)?;
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let p = entry?.path();
+                if p.is_dir() && !hidden(&p) {
+                    glob_from(&p, parts, files)?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if !part.contains(|c| c == '*' || c == '?') {
+        let p = dir.join(part);
+        if p.exists() {
+            glob_from(&p, rest, files)?;
+        }
+        return Ok(());
+    }
+
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let p = entry?.path();
+        let name = p.file_name().unwrap().to_string_lossy().to_string();
+        if !hidden(&p) && wildcard(part, &name) {
+            glob_from(&p, rest, files)?;
+        }
+    }
+    return Ok(());
+}
+
+// * matches any run of characters, ? any single one
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+
+    // position of the last * and the name position it was matched at, for backtracking
+    let (mut i, mut j) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while j < n.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == n[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            i = si + 1;
+            j = sj + 1;
+            star = Some((si, sj + 1));
+        } else {
+            return false;
+        }
+    }
+
+    return p[i..].iter().all(|&c| c == '*');
+}
+
+fn hidden(path: &Path) -> bool {
+    return match path.file_name() {
+        Some(n) => n.to_string_lossy().starts_with('.'),
+        None => false,
+    };
+}
+
+// Who wrote each file, from the index the crawler keeps next to what it saves. Files without an index
+// entry, or whose author wasn't found, are left out.
+pub fn authors(files: &Vec<PathBuf>) -> HashMap<PathBuf, String> {
+    let mut result = HashMap::new();
+
+    let mut dirs: Vec<&Path> = files.iter().filter_map(|f| f.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+
+    for dir in dirs {
+        let index = match read_file(dir.join(INDEX)) {
+            Ok(i) => i,
+            Err(_) => continue,
+        };
+        for line in index.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() >= 2 && !fields[1].trim().is_empty() {
+                result.insert(dir.join(fields[0]), fields[1].trim().to_string());
+            }
+        }
+    }
+
+    return result;
+}
+
+// Read files one at a time and hand each to f, so large corpora never have to be in memory at once.
+// Files that can't be read as text are reported and skipped.
+pub fn each_file<F: FnMut(&Path, &str) -> ()>(files: &Vec<PathBuf>, mut f: F) -> () {
+    for file in files {
+        match read_file(file) {
+            Ok(data) => f(file, &data),
+            Err(e) => eprintln!("skipping {:?}: {}", file, e),
+        }
+    }
+}
+
+// split off roughly /fraction/ of the files by taking every nth one, returns (held out, rest)
+pub fn split(files: Vec<PathBuf>, fraction: f64) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let n = (1.0 / fraction).round().max(1.0) as usize;
+
+    let mut held = Vec::new();
+    let mut rest = Vec::new();
+    files.into_iter().enumerate().for_each(|(i, f)| {
+        if i % n == n - 1 {
+            held.push(f);
+        } else {
+            rest.push(f);
+        }
+    });
+
+    return (held, rest);
 }
 
 // TODO: func(String) -> Vec<Lazy<String>>
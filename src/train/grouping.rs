@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::Error;
+
+// longest context any word gets unless told otherwise
+pub const DEFAULT_MAX_ORDER: u32 = 5;
+pub const DEFAULT_LOG_BASE: f64 = 10.0;
+
+// words that carry grammar rather than meaning, used by the function grouping
+const FUNCTION_WORDS: &[&str] = &[
+    "a", "an", "the", "this", "that", "these", "those", "some", "any", "no", "every", "each",
+    "i", "you", "he", "she", "it", "we", "they", "me", "him", "her", "us", "them", "my", "your", "his",
+    "its", "our", "their", "who", "whom", "which", "what",
+    "of", "in", "on", "at", "to", "for", "with", "by", "from", "about", "into", "over", "under", "after",
+    "before", "between", "through", "up", "down", "out", "off",
+    "and", "or", "but", "nor", "so", "yet", "if", "because", "as", "than", "when", "while", "though",
+    "is", "am", "are", "was", "were", "be", "been", "being", "have", "has", "had", "do", "does", "did",
+    "will", "would", "shall", "should", "can", "could", "may", "might", "must", "not",
+];
+
+// How words are assigned to groups. A word's group is the number of words, starting with it, used as
+// the context for whatever follows, so every strategy maps onto an n-gram order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Grouping {
+    // the original: a word's group is its corpus frequency, only the top /count/ frequencies (by
+    // number of words) are kept and the rest share the last one
+    Top(usize),
+    // one order per power of /base/ of the word's frequency
+    Log(f64),
+    // words ranked by frequency and cut into bins holding an equal share of the corpus
+    Quantile,
+    // function words get more context than content words, and words that look like verbs or adverbs
+    // by their -ing/-ed/-ly ending a little more than the rest. a guess from spelling, not tagging.
+    Function,
+    // every word the same
+    Fixed(u32),
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Grouping::Top(c) => write!(f, "top:{}", c),
+            Grouping::Log(b) => write!(f, "log:{}", b),
+            Grouping::Quantile => write!(f, "quantile"),
+            Grouping::Function => write!(f, "function"),
+            Grouping::Fixed(n) => write!(f, "fixed:{}", n),
+        };
+    }
+}
+
+// parse name[:parameter], e.g. `log:2`, `fixed:3` or `quantile`
+pub fn parse(spec: &str) -> Result<Grouping, Error> {
+    let mut parts = spec.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    let param = parts.next().map(|p| p.trim());
+    let bad = |p: &str| Error::Parse(format!("bad parameter {} for {} grouping", p, name));
+
+    return match (name, param) {
+        ("top", None) => Ok(Grouping::Top(1)),
+        ("top", Some(p)) => match p.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Grouping::Top(n)),
+            _ => Err(bad(p)),
+        },
+        ("log", None) => Ok(Grouping::Log(DEFAULT_LOG_BASE)),
+        ("log", Some(p)) => match p.parse::<f64>() {
+            Ok(b) if b > 1.0 => Ok(Grouping::Log(b)),
+            _ => Err(bad(p)),
+        },
+        ("quantile", None) => Ok(Grouping::Quantile),
+        ("function", None) => Ok(Grouping::Function),
+        ("fixed", None) => Ok(Grouping::Fixed(2)),
+        ("fixed", Some(p)) => match p.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(Grouping::Fixed(n)),
+            _ => Err(bad(p)),
+        },
+        _ => Err(Error::Parse(format!("unknown grouping {}, expected one of top[:count]|log[:base]|quantile|function|fixed[:n]",
+                                      spec))),
+    };
+}
+
+// what word frequencies are counted over before grouping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    // raw counts over everything
+    Corpus,
+    // counts weighted by how few documents, or sources, a word turns up in
+    Document,
+    Source,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Scope::Corpus => write!(f, "corpus"),
+            Scope::Document => write!(f, "document"),
+            Scope::Source => write!(f, "source"),
+        };
+    }
+}
+
+pub fn parse_scope(scope: &str) -> Result<Scope, Error> {
+    return match scope {
+        "corpus" => Ok(Scope::Corpus),
+        "document" => Ok(Scope::Document),
+        "source" => Ok(Scope::Source),
+        _ => Err(Error::Parse(format!("unknown group scope {}, expected one of corpus|document|source", scope))),
+    };
+}
+
+// Combine per document (or per source) counts into one frequency per word. Corpus scope just adds
+// them up; the others weight the total tf-idf style by ln(1 + units / units containing the word), so
+// words spread evenly over everything count for less than ones concentrated in a few places.
+pub fn frequencies(scope: Scope, units: Vec<HashMap<String, u32>>) -> HashMap<String, u32> {
+    let n = units.len() as f64;
+    let mut total: HashMap<String, (u32, u32)> = HashMap::new();
+    for unit in units {
+        unit.into_iter().for_each(|(w, c)| {
+            let e = total.entry(w).or_insert((0, 0));
+            e.0 += c;
+            e.1 += 1;
+        });
+    }
+
+    return total.into_iter()
+        .map(|(w, (tf, df))| {
+            if scope == Scope::Corpus {
+                return (w, tf);
+            }
+            let weight = tf as f64 * (1.0 + n / df as f64).ln();
+            return (w, (weight.round() as u32).max(1));
+        })
+        .collect();
+}
+
+// word -> group for every word in the frequency map, no group is ever above /max_order/
+pub fn assign(grouping: &Grouping, freq: HashMap<String, u32>, max_order: u32) -> HashMap<String, u32> {
+    let clamp = |g: u32| g.max(1).min(max_order);
+
+    return match grouping {
+        Grouping::Top(count) => top(freq, *count, max_order),
+        Grouping::Log(base) => freq.into_iter()
+            .map(|(w, f)| (w, clamp(1 + (f as f64).log(*base).floor() as u32)))
+            .collect(),
+        Grouping::Quantile => {
+            let total: u64 = freq.values().map(|f| *f as u64).sum();
+            let mut ranked: Vec<(String, u32)> = freq.into_iter().collect();
+            ranked.sort_by(|(k1, v1), (k2, v2)| v1.cmp(v2).then(k1.cmp(k2)));
+
+            // rarest first, a word's bin is how far through the corpus' tokens it falls
+            let mut seen: u64 = 0;
+            ranked.into_iter()
+                .map(|(w, f)| {
+                    seen += f as u64;
+                    let bin = (seen as f64 / total as f64 * max_order as f64).ceil() as u32;
+                    return (w, clamp(bin));
+                })
+                .collect()
+        }
+        Grouping::Function => freq.into_iter()
+            .map(|(w, _)| {
+                let g = if FUNCTION_WORDS.contains(&w.as_str()) {
+                    3
+                } else if w.ends_with("ing") || w.ends_with("ed") || w.ends_with("ly") {
+                    2
+                } else {
+                    1
+                };
+                return (w, clamp(g));
+            })
+            .collect(),
+        Grouping::Fixed(n) => freq.into_iter().map(|(w, _)| (w, clamp(*n))).collect(),
+    };
+}
+
+// the top /count/ frequencies by how many words have them, the rest go in with the last of those
+fn top(freq: HashMap<String, u32>, count: usize, max_order: u32) -> HashMap<String, u32> {
+    let mut gmap: HashMap<u32, Vec<String>> = HashMap::new();
+    freq.into_iter().for_each(|(k, v)| gmap.entry(v).or_insert_with(Vec::new).push(k));
+
+    let mut gvec: Vec<(u32, Vec<String>)> = gmap.into_iter().collect();
+    gvec.sort_by(|(g1, s1), (g2, s2)| s2.len().cmp(&s1.len()).then(g1.cmp(g2)));
+
+    let mut result = HashMap::new();
+    let mut last: u32 = 0;
+    for (i, (g, s)) in gvec.into_iter().enumerate() {
+        if i < count {
+            last = g.min(max_order);
+        }
+        s.into_iter().for_each(|w| {
+            result.insert(w, last);
+        });
+    }
+
+    return result;
+}
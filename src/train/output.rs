@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use crate::error::{self, Error};
+
+// how analysis results get printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // `[key] value` lines for reading in a terminal
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+pub fn parse_format(format: &str) -> Result<Format, Error> {
+    return match format {
+        "text" => Ok(Format::Text),
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Csv),
+        "tsv" => Ok(Format::Tsv),
+        _ => Err(Error::Parse(format!("unknown output format {}, expected one of text|json|csv|tsv", format))),
+    };
+}
+
+pub enum Cell {
+    Text(String),
+    Int(u64),
+    Float(f64),
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Cell::Text(s) => write!(f, "{}", s),
+            Cell::Int(i) => write!(f, "{}", i),
+            Cell::Float(x) => write!(f, "{:.6}", x),
+        };
+    }
+}
+
+impl From<String> for Cell {
+    fn from(s: String) -> Cell {
+        return Cell::Text(s);
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(s: &str) -> Cell {
+        return Cell::Text(s.to_string());
+    }
+}
+
+impl From<u64> for Cell {
+    fn from(i: u64) -> Cell {
+        return Cell::Int(i);
+    }
+}
+
+impl From<u32> for Cell {
+    fn from(i: u32) -> Cell {
+        return Cell::Int(i as u64);
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(i: usize) -> Cell {
+        return Cell::Int(i as u64);
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(x: f64) -> Cell {
+        return Cell::Float(x);
+    }
+}
+
+// rows of analysis results with named columns, already in the order they should be printed in
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(columns: &[&str]) -> Table {
+        return Table {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows: Vec::new(),
+        };
+    }
+
+    pub fn from_kv<K: Into<Cell>, V: Into<Cell>>(columns: &[&str], set: Vec<(K, V)>) -> Table {
+        let mut table = Table::new(columns);
+        set.into_iter().for_each(|(k, v)| table.push(vec![k.into(), v.into()]));
+        return table;
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) -> () {
+        self.rows.push(row);
+    }
+
+    // keep the first /first/ and last /last/ rows, nothing is dropped when both are 0
+    pub fn truncate(&mut self, first: usize, last: usize) -> () {
+        if (first == 0 && last == 0) || first + last >= self.rows.len() {
+            return;
+        }
+
+        let tail = self.rows.split_off(self.rows.len() - last);
+        self.rows.truncate(first);
+        self.rows.extend(tail);
+    }
+
+    // add a leading column holding the same value on every row, e.g. which source the rows are from
+    pub fn tag(&mut self, column: &str, value: &str) -> () {
+        self.columns.insert(0, column.to_string());
+        self.rows.iter_mut().for_each(|r| r.insert(0, Cell::from(value)));
+    }
+
+    pub fn append(&mut self, other: Table) -> () {
+        self.rows.extend(other.rows);
+    }
+}
+
+pub fn print(table: &Table, format: Format) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    return write(table, format, &mut out).map_err(error::io("failed to write output"));
+}
+
+pub fn write<W: Write>(table: &Table, format: Format, out: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for row in table.rows.iter() {
+                let rest: Vec<String> = row.iter().skip(1).map(|c| c.to_string()).collect();
+                writeln!(out, "[{}] {}", row[0], rest.join(" "))?;
+            }
+        }
+        Format::Csv | Format::Tsv => {
+            let (sep, escape): (&str, fn(&str) -> String) = if format == Format::Csv {
+                (",", csv_escape)
+            } else {
+                ("\t", tsv_escape)
+            };
+
+            let header: Vec<String> = table.columns.iter().map(|c| escape(c)).collect();
+            writeln!(out, "{}", header.join(sep))?;
+            for row in table.rows.iter() {
+                let cells: Vec<String> = row.iter().map(|c| escape(&c.to_string())).collect();
+                writeln!(out, "{}", cells.join(sep))?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, row) in table.rows.iter().enumerate() {
+                let fields: Vec<String> = table.columns.iter().zip(row.iter())
+                    .map(|(k, v)| format!("{}: {}", json_string(k), json_value(v)))
+                    .collect();
+                let comma = if i + 1 < table.rows.len() { "," } else { "" };
+                writeln!(out, "  {{{}}}{}", fields.join(", "), comma)?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+
+    return Ok(());
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        return format!("\"{}\"", s.replace('"', "\"\""));
+    }
+    return s.to_string();
+}
+
+fn tsv_escape(s: &str) -> String {
+    return s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r");
+}
+
+fn json_value(cell: &Cell) -> String {
+    return match cell {
+        Cell::Text(s) => json_string(s),
+        Cell::Int(i) => i.to_string(),
+        // json has no NaN or infinity
+        Cell::Float(x) if !x.is_finite() => "null".to_string(),
+        Cell::Float(x) => x.to_string(),
+    };
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
@@ -0,0 +1,311 @@
+use std::fs;
+
+use crate::error::{self, Error};
+use crate::train::output::{Cell, Table};
+
+const WIDTH: usize = 72;
+const HEIGHT: usize = 20;
+const BAR: usize = 50;
+
+const MARKERS: &[char] = &['*', 'o', '+', 'x', '#', '@'];
+const COLOURS: &[&str] = &["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    // both axes on a log scale, for rank/frequency and growth curves
+    LogLog,
+    Histogram,
+}
+
+pub struct Series {
+    pub name: String,
+    // (label, x, y), the label is what a histogram bar is called
+    pub points: Vec<(String, f64, f64)>,
+}
+
+pub struct Plot {
+    pub kind: Kind,
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+}
+
+impl Plot {
+    // plots of the same kind from several sources end up on one chart
+    pub fn merge(&mut self, other: Plot) -> Result<(), Error> {
+        if other.kind != self.kind || other.x_label != self.x_label {
+            return Err(Error::Parse("can't plot different kinds of results on the same chart".to_string()));
+        }
+        self.series.extend(other.series);
+        return Ok(());
+    }
+}
+
+// Work out what to draw from an analysis table: anything with a count is drawn as rank/frequency,
+// frequency groups and vocabulary growth as they are on log-log axes, length distributions as
+// histograms. Only the first /first/ and last /last/ points of each series are kept, as with
+// Table::truncate, but ranks are worked out over the whole table first.
+pub fn from_table(table: &Table, name: &str, first: usize, last: usize) -> Result<Plot, Error> {
+    let columns: Vec<&str> = table.columns.iter().map(|c| c.as_str()).collect();
+    let column = |c: &str| columns.iter().position(|x| *x == c);
+
+    if let Some(count) = column("count") {
+        // one line per n-gram length
+        let mut series: Vec<Series> = Vec::new();
+        for row in table.rows.iter() {
+            let n = match column("n") {
+                Some(i) => format!("{} {}-grams", name, row[i]),
+                None => name.to_string(),
+            };
+            if series.last().map(|s| s.name != n).unwrap_or(true) {
+                series.push(Series { name: n, points: Vec::new() });
+            }
+
+            series.last_mut().unwrap().points.push((row[0].to_string(), 0.0, number(&row[count])?));
+        }
+
+        // ranks only make sense in frequency order, labels move with their counts
+        series.iter_mut().for_each(|s| {
+            s.points.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+            s.points.iter_mut().enumerate().for_each(|(i, p)| p.1 = (i + 1) as f64);
+            s.points = slice(std::mem::replace(&mut s.points, Vec::new()), first, last);
+        });
+
+        return Ok(Plot {
+            kind: Kind::LogLog,
+            x_label: "rank".to_string(),
+            y_label: "frequency".to_string(),
+            series,
+        });
+    }
+
+    let kind = match columns.as_slice() {
+        ["frequency", "words"] | ["tokens", "types"] => Kind::LogLog,
+        ["words", "sentences"] | ["characters", "words"] | ["mark", "per_1000_words"] => Kind::Histogram,
+        _ => return Err(Error::Parse(format!("don't know how to plot {}", columns.join(", ")))),
+    };
+
+    let mut points = Vec::new();
+    for row in table.rows.iter() {
+        let x = if kind == Kind::Histogram { points.len() as f64 } else { number(&row[0])? };
+        points.push((row[0].to_string(), x, number(&row[1])?));
+    }
+    let mut points = slice(points, first, last);
+    if kind == Kind::LogLog {
+        // groups come out most common first, lines have to be drawn left to right
+        points.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    }
+
+    return Ok(Plot {
+        kind,
+        x_label: columns[0].to_string(),
+        y_label: columns[1].to_string(),
+        series: vec![Series { name: name.to_string(), points }],
+    });
+}
+
+// the first /first/ and last /last/ of /items/, all of them when both are 0
+fn slice<T>(mut items: Vec<T>, first: usize, last: usize) -> Vec<T> {
+    if (first == 0 && last == 0) || first + last >= items.len() {
+        return items;
+    }
+
+    let tail = items.split_off(items.len() - last);
+    items.truncate(first);
+    items.extend(tail);
+    return items;
+}
+
+fn number(cell: &Cell) -> Result<f64, Error> {
+    return match cell {
+        Cell::Int(i) => Ok(*i as f64),
+        Cell::Float(x) => Ok(*x),
+        Cell::Text(s) => Err(Error::Parse(format!("can't plot {}, it isn't a number", s))),
+    };
+}
+
+// write the plot to /path/ as an svg
+pub fn save(plot: &Plot, path: &str) -> Result<(), Error> {
+    return fs::write(path, svg(plot)).map_err(error::io(&format!("failed to write plot to {}", path)));
+}
+
+pub fn terminal(plot: &Plot) -> String {
+    let mut out = String::new();
+
+    if plot.kind == Kind::Histogram {
+        let max = plot.series.iter()
+            .flat_map(|s| s.points.iter().map(|(_, _, y)| *y))
+            .fold(0.0, f64::max);
+        let label_width = plot.series.iter()
+            .flat_map(|s| s.points.iter().map(|(l, _, _)| l.chars().count()))
+            .max()
+            .unwrap_or(0);
+
+        for s in plot.series.iter() {
+            out.push_str(&format!("{} ({} by {})\n", s.name, plot.y_label, plot.x_label));
+            for (label, _, y) in s.points.iter() {
+                let len = if max > 0.0 { (y / max * BAR as f64).round() as usize } else { 0 };
+                out.push_str(&format!("{:>w$} | {} {}\n", label, "#".repeat(len), value(*y), w = label_width));
+            }
+            out.push('\n');
+        }
+        return out;
+    }
+
+    let (x0, x1, y0, y1) = match bounds(plot) {
+        Some(b) => b,
+        None => return "nothing to plot\n".to_string(),
+    };
+
+    let mut grid = vec![vec![' '; WIDTH]; HEIGHT];
+    for (i, s) in plot.series.iter().enumerate() {
+        for (_, x, y) in s.points.iter().filter(|(_, x, y)| *x > 0.0 && *y > 0.0) {
+            let col = scale(x.log10(), x0, x1, WIDTH - 1);
+            let row = HEIGHT - 1 - scale(y.log10(), y0, y1, HEIGHT - 1);
+            grid[row][col] = MARKERS[i % MARKERS.len()];
+        }
+    }
+
+    let top = format!("{:.0}", 10f64.powf(y1));
+    let bottom = format!("{:.0}", 10f64.powf(y0));
+    let margin = top.len().max(bottom.len());
+
+    out.push_str(&format!("{} (log)\n", plot.y_label));
+    for (i, row) in grid.iter().enumerate() {
+        let label = if i == 0 { top.as_str() } else if i == HEIGHT - 1 { bottom.as_str() } else { "" };
+        out.push_str(&format!("{:>m$} |{}\n", label, row.iter().collect::<String>(), m = margin));
+    }
+    out.push_str(&format!("{:>m$} +{}\n", "", "-".repeat(WIDTH), m = margin));
+
+    let left = format!("{:.0}", 10f64.powf(x0));
+    let right = format!("{:.0}", 10f64.powf(x1));
+    out.push_str(&format!("{:>m$}  {}{:>w$}\n", "", left, right, m = margin, w = WIDTH - left.len()));
+    out.push_str(&format!("{:>m$}  {} (log)\n", "", plot.x_label, m = margin));
+
+    for (i, s) in plot.series.iter().enumerate() {
+        out.push_str(&format!("  {} {}\n", MARKERS[i % MARKERS.len()], s.name));
+    }
+    return out;
+}
+
+pub fn svg(plot: &Plot) -> String {
+    let (w, h) = (800.0, 500.0);
+    let (left, right, top, bottom) = (70.0, 20.0, 20.0, 70.0);
+    let (pw, ph) = (w - left - right, h - top - bottom);
+
+    let mut out = String::new();
+    out.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                           font-family=\"sans-serif\" font-size=\"12\">\n", w, h));
+    out.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"white\"/>\n", w, h));
+    out.push_str(&format!("<path d=\"M{l},{t} V{b} H{r}\" stroke=\"black\" fill=\"none\"/>\n",
+                          l = left, t = top, b = top + ph, r = left + pw));
+    out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                          left + pw / 2.0, h - 10.0, escape(&plot.x_label)));
+    out.push_str(&format!("<text x=\"15\" y=\"{}\" text-anchor=\"middle\" transform=\"rotate(-90 15 {})\">{}</text>\n",
+                          top + ph / 2.0, top + ph / 2.0, escape(&plot.y_label)));
+
+    if plot.kind == Kind::Histogram {
+        // sources needn't have the same lengths or marks, give every one seen a slot
+        let mut labels: Vec<&String> = Vec::new();
+        plot.series.iter().flat_map(|s| s.points.iter()).for_each(|(l, _, _)| {
+            if !labels.contains(&l) {
+                labels.push(l);
+            }
+        });
+        if labels.iter().all(|l| l.parse::<f64>().is_ok()) {
+            labels.sort_by(|a, b| a.parse::<f64>().unwrap().partial_cmp(&b.parse::<f64>().unwrap()).unwrap());
+        }
+        let max = plot.series.iter()
+            .flat_map(|s| s.points.iter().map(|(_, _, y)| *y))
+            .fold(0.0, f64::max);
+        let slot = pw / labels.len().max(1) as f64;
+        let bar = slot * 0.8 / plot.series.len() as f64;
+
+        for (i, s) in plot.series.iter().enumerate() {
+            for (label, _, y) in s.points.iter() {
+                let j = labels.iter().position(|l| *l == label).unwrap();
+                let bh = if max > 0.0 { y / max * ph } else { 0.0 };
+                out.push_str(&format!("<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{}</title></rect>\n",
+                                      left + j as f64 * slot + slot * 0.1 + i as f64 * bar, top + ph - bh, bar, bh,
+                                      COLOURS[i % COLOURS.len()], value(*y)));
+            }
+        }
+        for (j, l) in labels.iter().enumerate() {
+            out.push_str(&format!("<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                                  left + (j as f64 + 0.5) * slot, top + ph + 15.0, escape(l)));
+        }
+        out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n", left - 5.0, top + 5.0, value(max)));
+    } else if let Some((x0, x1, y0, y1)) = bounds(plot) {
+        let px = |x: f64| left + (x.log10() - x0) / (x1 - x0).max(1e-9) * pw;
+        let py = |y: f64| top + ph - (y.log10() - y0) / (y1 - y0).max(1e-9) * ph;
+
+        // a tick for every power of ten
+        for d in x0.floor() as i32..=x1.ceil() as i32 {
+            let x = 10f64.powi(d);
+            if x.log10() >= x0 && x.log10() <= x1 {
+                out.push_str(&format!("<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n", px(x), top + ph + 15.0, x));
+            }
+        }
+        for d in y0.floor() as i32..=y1.ceil() as i32 {
+            let y = 10f64.powi(d);
+            if y.log10() >= y0 && y.log10() <= y1 {
+                out.push_str(&format!("<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n", left - 5.0, py(y) + 4.0, y));
+            }
+        }
+
+        for (i, s) in plot.series.iter().enumerate() {
+            let points: Vec<String> = s.points.iter()
+                .filter(|(_, x, y)| *x > 0.0 && *y > 0.0)
+                .map(|(_, x, y)| format!("{:.1},{:.1}", px(*x), py(*y)))
+                .collect();
+            out.push_str(&format!("<polyline points=\"{}\" fill=\"none\" stroke=\"{}\"/>\n",
+                                  points.join(" "), COLOURS[i % COLOURS.len()]));
+        }
+    }
+
+    for (i, s) in plot.series.iter().enumerate() {
+        let y = top + 15.0 + i as f64 * 15.0;
+        out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"{}\">{}</text>\n",
+                              w - right - 5.0, y, COLOURS[i % COLOURS.len()], escape(&s.name)));
+    }
+
+    out.push_str("</svg>\n");
+    return out;
+}
+
+// log10 bounds over all positive points: (x min, x max, y min, y max)
+fn bounds(plot: &Plot) -> Option<(f64, f64, f64, f64)> {
+    let points: Vec<(f64, f64)> = plot.series.iter()
+        .flat_map(|s| s.points.iter())
+        .filter(|(_, x, y)| *x > 0.0 && *y > 0.0)
+        .map(|(_, x, y)| (x.log10(), y.log10()))
+        .collect();
+    if points.is_empty() {
+        return None;
+    }
+
+    let x0 = points.iter().map(|p| p.0).fold(std::f64::INFINITY, f64::min);
+    let x1 = points.iter().map(|p| p.0).fold(std::f64::NEG_INFINITY, f64::max);
+    let y0 = points.iter().map(|p| p.1).fold(std::f64::INFINITY, f64::min);
+    let y1 = points.iter().map(|p| p.1).fold(std::f64::NEG_INFINITY, f64::max);
+    return Some((x0, x1, y0, y1));
+}
+
+fn scale(v: f64, min: f64, max: f64, steps: usize) -> usize {
+    if max <= min {
+        return 0;
+    }
+    return ((v - min) / (max - min) * steps as f64).round() as usize;
+}
+
+// counts as whole numbers, rates to a couple of places
+fn value(y: f64) -> String {
+    if y.fract() == 0.0 {
+        return format!("{}", y);
+    }
+    return format!("{:.2}", y);
+}
+
+fn escape(s: &str) -> String {
+    return s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
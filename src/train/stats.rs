@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use crate::train::analyse::create_corpus;
+use crate::train::data::each_file;
+
+pub const DEFAULT_WINDOW: usize = 500;
+
+pub struct Stats {
+    pub tokens: u64,
+    pub types: u64,
+    // words seen exactly once
+    pub hapax: u64,
+    pub ttr: f64,
+    // moving average type-token ratio over /window/ tokens, unlike ttr it doesn't shrink with corpus size
+    pub mattr: f64,
+    pub window: usize,
+    pub yules_k: f64,
+
+    // frequency ~ rank^-exponent, fitted on log-log scale
+    pub zipf_exponent: f64,
+    pub zipf_r2: f64,
+
+    // types ~ k * tokens^beta
+    pub heaps_k: f64,
+    pub heaps_beta: f64,
+    pub heaps_r2: f64,
+
+    // (tokens read, types seen so far) at roughly geometric intervals
+    pub curve: Vec<(u64, u64)>,
+}
+
+// Lexical statistics over all documents read as a single stream of tokens.
+pub fn stats_cmd(files: &Vec<PathBuf>, window: usize) -> Stats {
+    let mut freq: HashMap<String, u64> = HashMap::new();
+    let mut tokens: u64 = 0;
+
+    let mut curve: Vec<(u64, u64)> = Vec::new();
+    let mut checkpoint: u64 = 1;
+
+    // sliding window for mattr
+    let mut recent: VecDeque<String> = VecDeque::with_capacity(window + 1);
+    let mut in_window: HashMap<String, u64> = HashMap::new();
+    let mut ttr_sum: f64 = 0.0;
+    let mut windows: u64 = 0;
+
+    each_file(files, |_, data| {
+        for w in create_corpus(data) {
+            tokens += 1;
+            *freq.entry(w.to_string()).or_insert(0) += 1;
+
+            if tokens == checkpoint {
+                curve.push((tokens, freq.len() as u64));
+                checkpoint = (checkpoint + 1).max((checkpoint as f64 * 1.1) as u64);
+            }
+
+            *in_window.entry(w.to_string()).or_insert(0) += 1;
+            recent.push_back(w);
+            if recent.len() > window {
+                let old = recent.pop_front().unwrap();
+                let c = in_window.get_mut(&old).unwrap();
+                *c -= 1;
+                if *c == 0 {
+                    in_window.remove(&old);
+                }
+            }
+            if recent.len() == window {
+                ttr_sum += in_window.len() as f64 / window as f64;
+                windows += 1;
+            }
+        }
+    });
+
+    let types = freq.len() as u64;
+    if curve.last().map(|(t, _)| *t) != Some(tokens) && tokens > 0 {
+        curve.push((tokens, types));
+    }
+
+    let ttr = if tokens == 0 { 0.0 } else { types as f64 / tokens as f64 };
+
+    // number of types for each frequency
+    let mut spectrum: HashMap<u64, u64> = HashMap::new();
+    freq.values().for_each(|f| *spectrum.entry(*f).or_insert(0) += 1);
+
+    let n = tokens as f64;
+    let sum: f64 = spectrum.iter().map(|(i, v)| (*i as f64).powi(2) * *v as f64).sum();
+    let yules_k = if tokens == 0 { 0.0 } else { 10_000.0 * (sum - n) / (n * n) };
+
+    let mut ranked: Vec<u64> = freq.values().cloned().collect();
+    ranked.sort_by(|a, b| b.cmp(a));
+    let zipf: Vec<(f64, f64)> = ranked.iter().enumerate()
+        .map(|(r, f)| (((r + 1) as f64).ln(), (*f as f64).ln()))
+        .collect();
+    let (_, zipf_slope, zipf_r2) = fit(&zipf);
+
+    let heaps: Vec<(f64, f64)> = curve.iter()
+        .map(|(t, v)| ((*t as f64).ln(), (*v as f64).ln()))
+        .collect();
+    let (heaps_intercept, heaps_beta, heaps_r2) = fit(&heaps);
+
+    return Stats {
+        tokens,
+        types,
+        hapax: *spectrum.get(&1).unwrap_or(&0),
+        ttr,
+        mattr: if windows == 0 { ttr } else { ttr_sum / windows as f64 },
+        window,
+        yules_k,
+        zipf_exponent: -zipf_slope,
+        zipf_r2,
+        heaps_k: heaps_intercept.exp(),
+        heaps_beta,
+        heaps_r2,
+        curve,
+    };
+}
+
+// least squares line through the points, returns (intercept, slope, r squared)
+fn fit(points: &Vec<(f64, f64)>) -> (f64, f64, f64) {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let syy: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+
+    if sxx == 0.0 {
+        return (mean_y, 0.0, 0.0);
+    }
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let r2 = if syy == 0.0 { 1.0 } else { sxy * sxy / (sxx * syy) };
+    return (intercept, slope, r2);
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::train::analyse::{create_corpus, sentences, tokenize};
+use crate::train::data::each_file;
+
+pub struct Style {
+    pub sentences: u64,
+    pub words: u64,
+    pub syllables: u64,
+    // words of three or more syllables, for the fog index
+    pub complex: u64,
+    // words spoken inside quotation marks
+    pub dialogue: u64,
+
+    // words per sentence -> number of sentences
+    pub sentence_lengths: BTreeMap<usize, u64>,
+    // characters per word -> number of words
+    pub word_lengths: BTreeMap<usize, u64>,
+    pub punctuation: BTreeMap<char, u64>,
+}
+
+impl Style {
+    pub fn mean_sentence(&self) -> f64 {
+        return ratio(self.words, self.sentences);
+    }
+
+    pub fn mean_word(&self) -> f64 {
+        let chars: u64 = self.word_lengths.iter().map(|(l, c)| *l as u64 * c).sum();
+        return ratio(chars, self.words);
+    }
+
+    pub fn flesch_reading_ease(&self) -> f64 {
+        return 206.835 - 1.015 * self.mean_sentence() - 84.6 * ratio(self.syllables, self.words);
+    }
+
+    pub fn flesch_kincaid_grade(&self) -> f64 {
+        return 0.39 * self.mean_sentence() + 11.8 * ratio(self.syllables, self.words) - 15.59;
+    }
+
+    pub fn gunning_fog(&self) -> f64 {
+        return 0.4 * (self.mean_sentence() + 100.0 * ratio(self.complex, self.words));
+    }
+
+    pub fn dialogue_ratio(&self) -> f64 {
+        return ratio(self.dialogue, self.words);
+    }
+
+    // punctuation marks per 1000 words
+    pub fn punctuation_rate(&self) -> Vec<(String, f64)> {
+        return self.punctuation.iter()
+            .map(|(p, c)| (p.to_string(), ratio(*c, self.words) * 1000.0))
+            .collect();
+    }
+}
+
+// Readability and style measures. Works on the raw text rather than the training tokens since
+// sentences, quotes and punctuation are exactly what create_corpus throws away; generated text
+// saved with `generate -o` can be measured the same way as the corpus it came from.
+pub fn style_cmd(files: &Vec<PathBuf>) -> Style {
+    let mut style = Style {
+        sentences: 0,
+        words: 0,
+        syllables: 0,
+        complex: 0,
+        dialogue: 0,
+        sentence_lengths: BTreeMap::new(),
+        word_lengths: BTreeMap::new(),
+        punctuation: BTreeMap::new(),
+    };
+
+    each_file(files, |_, data| {
+        for sentence in sentences(data) {
+            let words = create_corpus(sentence);
+            if words.is_empty() {
+                continue;
+            }
+
+            style.sentences += 1;
+            *style.sentence_lengths.entry(words.len()).or_insert(0) += 1;
+
+            for w in words.iter() {
+                let s = syllables(w);
+                style.words += 1;
+                style.syllables += s;
+                if s >= 3 {
+                    style.complex += 1;
+                }
+                *style.word_lengths.entry(w.chars().count()).or_insert(0) += 1;
+            }
+        }
+
+        data.chars()
+            .filter(|c| c.is_ascii_punctuation() || "“”‘’…—–".contains(*c))
+            .for_each(|c| *style.punctuation.entry(c).or_insert(0) += 1);
+
+        // both come out in text order, so walk them together
+        let quoted = quoted(data);
+        let mut q = 0;
+        for (start, _, _) in tokenize(data) {
+            while q < quoted.len() && quoted[q].1 <= start {
+                q += 1;
+            }
+            if q < quoted.len() && quoted[q].0 <= start {
+                style.dialogue += 1;
+            }
+        }
+    });
+
+    return style;
+}
+
+// byte ranges inside quotation marks. straight quotes toggle, curly quotes open and close; a
+// paragraph break ends any quote left open, as fiction often doesn't close it.
+fn quoted(data: &str) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut previous = ' ';
+
+    for (i, c) in data.char_indices() {
+        match (c, open) {
+            ('"', None) | ('“', None) => open = Some(i),
+            ('"', Some(s)) | ('”', Some(s)) => {
+                result.push((s, i));
+                open = None;
+            }
+            ('\n', Some(s)) if previous == '\n' => {
+                result.push((s, i));
+                open = None;
+            }
+            ('⏺', Some(s)) => {
+                result.push((s, i));
+                open = None;
+            }
+            _ => (),
+        }
+        previous = c;
+    }
+    if let Some(s) = open {
+        result.push((s, data.len()));
+    }
+
+    return result;
+}
+
+// vowel groups, less a silent e at the end; good enough for english readability formulas
+fn syllables(word: &str) -> u64 {
+    let vowel = |c: char| "aeiouy".contains(c);
+    let chars: Vec<char> = word.chars().collect();
+
+    let mut count = 0;
+    let mut previous = false;
+    for c in chars.iter() {
+        let v = vowel(*c);
+        if v && !previous {
+            count += 1;
+        }
+        previous = v;
+    }
+
+    let n = chars.len();
+    if n > 2 && chars[n - 1] == 'e' && !vowel(chars[n - 2]) && !(chars[n - 2] == 'l' && !vowel(chars[n - 3])) {
+        count -= 1;
+    }
+
+    return count.max(1);
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        return 0.0;
+    }
+    return a as f64 / b as f64;
+}
@@ -1,153 +1,266 @@
-extern crate sled;
-
-use sled::Db;
 use std::path::PathBuf;
 use crate::train::data::read_file;
-use crate::train::analyse::create_corpus;
-use std::convert::TryInto;
-use std::collections::{HashMap, HashSet};
-use self::sled::{IVec, Tree};
+use crate::train::analyse::{char_corpus, sentence_corpus};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::error::{self, Error};
+use crate::model;
+use crate::model::store::{self, ChainStore};
+use crate::model::smoothing::{self, Smoothing};
+use crate::train::bpe::Bpe;
+use crate::train::grouping;
+use crate::train::grouping::{Grouping, Scope};
+use crate::train::vocab::{self, Vocabulary};
 
 pub struct Persistent {
-    db: Db
+    store: Box<dyn ChainStore>,
+
+    // also train following n-gram -> preceding word chains
+    reverse: bool,
+
+    // how documents are split into tokens, words unless training a character or subword model
+    tokenize: Box<dyn Fn(&str) -> Vec<String>>,
+
+    // (min count, max vocabulary, use <unk> classes) when rare words are to be replaced, groups
+    // works out the vocabulary from it for train to use
+    pruning: Option<(u64, Option<usize>, bool)>,
+    vocabulary: Option<Vocabulary>,
+
+    summary: Summary,
+}
+
+// what groups and train did, for the train command to report
+pub struct Summary {
+    // (words kept, words seen) when rare words were replaced
+    pub vocabulary: Option<(usize, usize)>,
+    // group -> how many words are in it
+    pub groups: BTreeMap<u32, usize>,
+    // files trained on, held out ones aren't
+    pub files: usize,
 }
 
 impl Persistent {
-    pub fn groups(&mut self, count: usize, files: &Vec<PathBuf>) -> () {
-        //TODO: calculating word frequency across corpus here, what if I do it per document?
-        //  * grouping would still have to be done globally
-        let mut freq: HashMap<String, u32> = HashMap::new();
+    // Forget an earlier training run: its groups and chains, or retraining would add to the old
+    // counts, and its settings, so retraining without an option doesn't leave it in force. A subword
+    // tokenizer stays, it's learned into the db before training on purpose.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.store.clear()?;
+        for key in [model::HOLDOUT, model::SMOOTHING, model::REVERSE, model::GROUPING, model::GROUP_SCOPE,
+                    model::CHAINS, model::SENTENCES, model::UNKNOWN_CLASSES].iter() {
+            self.store.remove_meta(key)?;
+        }
+        if model::load_bpe(&*self.store)?.is_none() {
+            self.store.remove_meta(model::UNIT)?;
+        }
+        return self.store.clear_rare();
+    }
 
-        for file in files {
-            let data = read_file(file).unwrap(); //todo proper error handling
+    // remember which documents were not trained on, `evaluate` scores them when given no path
+    pub fn holdout(&self, files: &Vec<PathBuf>) -> Result<(), Error> {
+        let paths: Vec<String> = files.iter()
+            .map(|f| f.to_string_lossy().to_string())
+            .collect();
+        return model::set_meta(&*self.store, model::HOLDOUT, &paths);
+    }
 
-            // build map of word frequency
-            create_corpus(&data).into_iter()
-                .for_each(|w| *freq.entry(w).or_insert(0) += 1);
+    // train reverse chains as well, generate needs them to work backwards from an ending or keyword
+    pub fn reverse(&mut self) -> Result<(), Error> {
+        self.reverse = true;
+        return model::set_meta(&*self.store, model::REVERSE, &true);
+    }
+
+    // split words into the pieces of a subword tokenizer, train picks it up again from the db
+    pub fn bpe(&mut self, bpe: &Bpe) -> Result<(), Error> {
+        // chains trained on whole words would be read as pieces and glued into one run of text
+        if model::get_meta::<Vec<u32>>(&*self.store, model::CHAINS)?.is_some() {
+            return Err(Error::Model("db already has trained chains, learn the tokenizer into a new db and train on that".to_string()));
         }
+        model::set_meta(&*self.store, model::UNIT, &model::BPE_UNIT.to_string())?;
+        return model::set_meta(&*self.store, model::BPE_MERGES, &bpe.merges);
+    }
 
-        // calculate groups
-        let mut gmap: HashMap<u32, (u32, HashSet<String>)> = HashMap::new();
-        freq.into_iter().for_each(|(k, v)| {
-            match gmap.get_mut(&v) {
-                Some((g, s)) => {
-                    *g += 1;
-                    s.insert(k);
-                }
-                None => {
-                    let mut s: HashSet<String> = HashSet::new();
-                    s.insert(k);
+    // train on characters rather than words, for making up new words
+    pub fn chars(&mut self) -> Result<(), Error> {
+        self.tokenize = Box::new(char_corpus);
+        return model::set_meta(&*self.store, model::UNIT, &model::CHAR_UNIT.to_string());
+    }
 
-                    gmap.insert(v, (1, s));
-                }
+    // replace words seen fewer than /min_count/ times, or outside the /max_vocab/ most frequent,
+    // with <unk> or one of its classes
+    pub fn prune(&mut self, min_count: u64, max_vocab: Option<usize>, classes: bool) -> () {
+        self.pruning = Some((min_count, max_vocab, classes));
+    }
+
+    // smoothing that evaluate, export and generate use for this db unless told otherwise
+    pub fn smoothing(&self, smoothing: Smoothing) -> Result<(), Error> {
+        return model::set_meta(&*self.store, model::SMOOTHING, &smoothing.to_string());
+    }
+
+    // Work out each word's group from its frequency, see grouping::Grouping for the ways of doing
+    // that. /units/ are the documents or sources frequencies are counted per, see grouping::Scope.
+    // The grouping is kept in the db meta alongside the group map so both can be looked up later.
+    pub fn groups(&mut self, grouping: &Grouping, max_order: u32, scope: Scope, units: &Vec<Vec<PathBuf>>) -> Result<(), Error> {
+        let mut counts: Vec<HashMap<String, u32>> = Vec::new();
+        for files in units {
+            let mut freq: HashMap<String, u32> = HashMap::new();
+            for file in files {
+                let data = read_file(file).map_err(error::io(&format!("couldnt read {:?}", file)))?;
+
+                // build map of word frequency
+                (self.tokenize)(&data).into_iter()
+                    .for_each(|w| *freq.entry(w).or_insert(0) += 1);
             }
-        });
-
-        // calculate top /count/ groups
-        let mut gvec: Vec<(u32, (u32, HashSet<String>))> = Vec::new();
-        gmap.into_iter().for_each(|e| gvec.push(e));
-        gvec.sort_by(|(_, (c1, _)), (_, (c2, _))| c2.cmp(c1));
-
-        let mut last: u32 = 0;
-
-        // persist word -> group map for top _count_ groups
-        let groups = self.db.open_tree("groups").unwrap();
-        for (i, (g, (_, s))) in gvec.into_iter().enumerate() {
-            if i < count {
-                s.into_iter().for_each(|w| {
-                    //todo proper error handling
-                    groups.insert(w, u32_to_ivec(g)).unwrap();
-                    return;
-                });
-                last = g
-            } else {
-                s.into_iter().for_each(|w| {
-                    groups.insert(w, u32_to_ivec(last)).unwrap();
-                })
+            counts.push(freq);
+        }
+
+        if let Some((min_count, max_vocab, classes)) = self.pruning {
+            let mut total: HashMap<String, u64> = HashMap::new();
+            counts.iter().flat_map(|c| c.iter()).for_each(|(w, c)| *total.entry(w.to_string()).or_insert(0) += *c as u64);
+
+            let vocabulary = Vocabulary {
+                keep: vocab::select(&total, min_count, max_vocab),
+                classes,
+            };
+            self.summary.vocabulary = Some((vocabulary.keep.len(), total.len()));
+
+            // generate puts rare words back where the model says <unk>
+            for (class, words) in vocab::rare(&total, &vocabulary) {
+                self.store.set_rare(&class, &words)?;
             }
+            model::set_meta(&*self.store, model::UNKNOWN_CLASSES, &classes)?;
+
+            counts = counts.into_iter()
+                .map(|c| {
+                    let mut mapped: HashMap<String, u32> = HashMap::new();
+                    let (words, n): (Vec<String>, Vec<u32>) = c.into_iter().unzip();
+                    vocabulary.map(words).into_iter().zip(n.into_iter())
+                        .for_each(|(w, n)| *mapped.entry(w).or_insert(0) += n);
+                    return mapped;
+                })
+                .collect();
+            self.vocabulary = Some(vocabulary);
         }
+        let freq = grouping::frequencies(scope, counts);
+
+        // persist word -> group map
+        let mut sizes: BTreeMap<u32, usize> = BTreeMap::new();
+        for (w, g) in grouping::assign(grouping, freq, max_order) {
+            self.store.set_group(&w, g)?;
+            *sizes.entry(g).or_insert(0) += 1;
+        }
+
+        self.summary.groups = sizes;
+        model::set_meta(&*self.store, model::GROUPING, &grouping.to_string())?;
+        return model::set_meta(&*self.store, model::GROUP_SCOPE, &scope.to_string());
     }
 
     // Now, we can train n markov chains simultaneously, deciding which one to put our words in
     // based on their group. Each group is a separate markov chain trained on the same corpus.
     // NB:
     //   we will need to keep a stack of the last x words, where x == largest group
-    pub fn train(self, files: &Vec<PathBuf>) -> () {
-        let groups = self.db.open_tree("groups").unwrap();
-
-        // create m * n-grams
-        let mut chains: HashMap<u32, Tree> = HashMap::new();
-        groups.iter()
-            .fold(HashSet::new(), |mut s, v| {
-                let (_, g) = v.unwrap();
-                s.insert(ivec_to_u32(g));
-                return s;
-            })
-            .into_iter().for_each(|g| {
-            chains.insert(g, self.db.open_tree(u32_to_ivec(g)).unwrap());
-        });
+    pub fn train(&mut self, files: &Vec<PathBuf>) -> Result<(), Error> {
+        // m * n-grams: the last few words of a document can end up in a shorter chain than their
+        // group would give them
+        let mut orders: HashSet<u32> = HashSet::new();
 
         for file in files {
-            let data = read_file(file).unwrap();
-            let words = create_corpus(&data);
+            let data = read_file(file).map_err(error::io(&format!("couldnt read {:?}", file)))?;
+            let mut words = (self.tokenize)(&data);
+            if let Some(v) = self.vocabulary.as_ref() {
+                words = v.map(words);
+            }
 
-            for (i, w) in words.iter().enumerate() {
-                //NB: we have parsed this corpus before so |w| should exist, but probably this can
-                // be cleaner
-                let g: u32 = ivec_to_u32(groups.get(w).unwrap().unwrap());
+            // every word but the final </d> predicts something
+            for i in 0..words.len().saturating_sub(1) {
+                let g: u32 = self.group(&words[i])?;
 
-                if i + g as usize >= words.len() {
-                    //todo consider any remaining words - these might be g=1
-                    break;
-                }
+                // near the end the context is cut short instead of the words being dropped, so
+                // endings are learned too
+                let n = (g as usize).max(1).min(words.len() - 1 - i);
 
                 //finally at the crux of all the above logic: group # is the n in n-gram is the key size
-                let key: Vec<String> = words[i..i + g as usize].to_vec();
-                let skey = bincode::serialize(&key).unwrap();
+                self.store.increment(n as u32, false, &words[i..i + n], &words[i + n])?;
+                orders.insert(n as u32);
+            }
+
+            // the mirror image of the above: the group of a word decides how many words, read
+            // backwards from it, predict the word before them. keys are stored nearest word first
+            // so the chains look like forward chains trained on reversed text.
+            if self.reverse {
+                for i in 1..words.len() {
+                    let g: u32 = self.group(&words[i])?;
+                    let n = (g as usize).max(1).min(i);
 
-                chains.get(&g).unwrap()
-                    .update_and_fetch(skey, partial_application::partial!(add_to_chain, words[i+g as usize].to_string(), _))
-                    .unwrap();
+                    let key: Vec<String> = words[i + 1 - n..=i].iter().rev().cloned().collect();
+                    self.store.increment(n as u32, true, &key, &words[i - n])?;
+                    orders.insert(n as u32);
+                }
             }
         }
 
-        chains.iter().for_each(|(k, v)| {
-            println!("{}\n------------------", k);
-            v.iter().for_each(|r| {
-                let (k2, v2) = r.unwrap();
-                let key: Vec<String> = bincode::deserialize(&k2).unwrap();
-                let value: HashSet<String> = bincode::deserialize(&v2).unwrap();
-                println!("{:?} {:?}", key, value)
-            });
-        })
+        // which chains exist, model::load can't tell from the groups alone any more
+        let mut orders: Vec<u32> = orders.into_iter().collect();
+        orders.sort();
+        model::set_meta(&*self.store, model::CHAINS, &orders)?;
+        model::set_meta(&*self.store, model::SENTENCES, &true)?;
+        self.summary.files = files.len();
+        return self.store.flush();
+    }
+
+    // words were all grouped before training, one that wasn't means the corpus changed in between
+    fn group(&self, word: &str) -> Result<u32, Error> {
+        return self.store.group(word)?
+            .ok_or_else(|| Error::Model(format!("{} has no group, was the corpus changed while training?", word)));
     }
 }
 
-fn add_to_chain(word: String, old: Option<&[u8]>) -> Option<Vec<u8>> {
-    let set: HashSet<String> = match old {
-        Some(b) => {
-            let mut s: HashSet<String> = bincode::deserialize(b.try_into().unwrap()).unwrap();
-//            println!("INSERTING TO EXISTING {:?} <- {}", s, word);
-            s.insert(word);
-            s
-        }
-        None => {
-            let mut s: HashSet<String> = HashSet::new();
-            s.insert(word);
-            s
-        }
-    };
+impl Persistent {
+    // held out perplexity of what was just trained, with the smoothing it was trained for
+    pub fn perplexity(&self, files: &Vec<PathBuf>) -> Result<f64, Error> {
+        let smoothing = smoothing::select(&*self.store, None)?;
+        return model::perplexity(&*self.store, smoothing, files);
+    }
+
+    // files put aside with holdout, empty if none were
+    pub fn held_out(&self) -> Result<Vec<PathBuf>, Error> {
+        return Ok(model::held_out(&*self.store)?.unwrap_or_default());
+    }
+
+    pub fn summary(&self) -> &Summary {
+        return &self.summary;
+    }
+
+    // every word with its group, by group then word
+    pub fn group_map(&self) -> Result<Vec<(String, u32)>, Error> {
+        let mut result = self.store.groups()?;
 
-    // serialise
-    let serialized = bincode::serialize(&set).unwrap();
-    return Some(serialized);
+        result.sort_by(|(k1, g1), (k2, g2)| g1.cmp(g2).then(k1.cmp(k2)));
+        return Ok(result);
+    }
+
+    // (grouping, scope) the group map was built with, dbs from before either existed say nothing
+    pub fn grouping(&self) -> Result<(Option<String>, Option<String>), Error> {
+        return Ok((model::get_meta(&*self.store, model::GROUPING)?, model::get_meta(&*self.store, model::GROUP_SCOPE)?));
+    }
+}
+
+pub fn new(db_path: &str) -> Result<Persistent, Error> {
+    return with_store(Box::new(store::create(db_path)?));
 }
 
-pub fn new(db_path: &str) -> Result<Persistent, String> {
-    return match Db::open(db_path) {
-        Ok(d) => Ok(Persistent { db: d }),
-        Err(e) => Err(e.to_string()),
+// a db trained earlier, to look at rather than train
+pub fn open(db_path: &str) -> Result<Persistent, Error> {
+    return with_store(Box::new(store::open(db_path)?));
+}
+
+// train into any store, e.g. store::memory() for a model that needn't outlive the process
+pub fn with_store(store: Box<dyn ChainStore>) -> Result<Persistent, Error> {
+    // a subword tokenizer learned into the store beforehand is picked up here
+    let tokenize: Box<dyn Fn(&str) -> Vec<String>> = match model::load_bpe(&*store)? {
+        Some(b) => Box::new(move |data| b.tokenize(data)),
+        None => Box::new(sentence_corpus),
     };
+    let summary = Summary { vocabulary: None, groups: BTreeMap::new(), files: 0 };
+    return Ok(Persistent { store, reverse: false, tokenize, pruning: None, vocabulary: None, summary });
 }
 
 //fn print_tree(t: sled::Tree) -> () {
@@ -166,11 +279,109 @@ pub fn new(db_path: &str) -> Result<Persistent, String> {
 //    })
 //}
 
-fn u32_to_ivec(x: u32) -> IVec {
-    IVec::from(x.to_be_bytes().to_vec())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+    use crate::model::compiled;
+
+    // a small corpus in a directory of its own, tests run side by side
+    fn corpus(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let dir = env::temp_dir().join(format!("rustygenmo-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PathBuf> = vec![
+            ("a.txt", "The cat sat on the mat. The dog sat on the cat. A cat is not a dog."),
+            ("b.txt", "The dog ran. The cat ran after the dog, and the mat stayed where it was."),
+        ].into_iter().map(|(name, text)| {
+            let path = dir.join(name);
+            fs::write(&path, text).unwrap();
+            path
+        }).collect();
+        return (dir, files);
+    }
 
-fn ivec_to_u32(x: IVec) -> u32 {
-    let a: [u8; 4] = x.to_vec().as_slice().try_into().unwrap();
-    return u32::from_be_bytes(a);
+    fn trained(store: Box<dyn ChainStore>, files: &Vec<PathBuf>) -> Persistent {
+        let mut chain = with_store(store).unwrap();
+        chain.reverse().unwrap();
+        chain.groups(&Grouping::Top(3), 3, Scope::Corpus, &vec![files.clone()]).unwrap();
+        chain.train(files).unwrap();
+        return chain;
+    }
+
+    // both backends have to come out with the same model from the same corpus
+    #[test]
+    fn memory_matches_sled() {
+        let (dir, files) = corpus("store");
+
+        let memory = trained(Box::new(store::memory()), &files);
+        let sled = trained(Box::new(store::create(dir.join("db").to_str().unwrap()).unwrap()), &files);
+
+        for reverse in &[false, true] {
+            let (m, s) = match reverse {
+                false => (model::load(&*memory.store).unwrap(), model::load(&*sled.store).unwrap()),
+                true => (model::load_reverse(&*memory.store).unwrap().unwrap(), model::load_reverse(&*sled.store).unwrap().unwrap()),
+            };
+            assert!(!m.contexts.is_empty());
+            assert_eq!(m.contexts, s.contexts);
+            assert_eq!(m.vocabulary, s.vocabulary);
+            assert_eq!(m.order, s.order);
+        }
+
+        drop(sled);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A compiled model has to give what the estimator it was compiled from gives, for contexts it
+    // stored, ones it never saw and words outside its vocabulary. Only full length contexts are
+    // compared, ARPA style Kneser-Ney keeps the lower order estimates for the shorter ones.
+    #[test]
+    fn compiled_matches_db() {
+        let (dir, files) = corpus("compiled");
+        let chain = trained(Box::new(store::memory()), &files);
+        let counts = model::load(&*chain.store).unwrap();
+        let n = counts.order - 1;
+        let zebra = "zebra".to_string();
+
+        let mut words: Vec<String> = counts.vocabulary.iter().cloned().collect();
+        words.sort();
+        words.push(zebra.clone());
+        let mut contexts: Vec<Vec<String>> = counts.contexts.keys().filter(|k| k.len() == n).cloned().collect();
+        contexts.sort();
+
+        let c = &contexts[0];
+        let reversed: Vec<String> = c.iter().rev().cloned().collect();
+        let mut unseen: Vec<Vec<String>> = vec![
+            vec![zebra.clone(); n],
+            c[..n - 1].iter().chain(Some(&zebra)).cloned().collect(),
+            Some(&zebra).into_iter().chain(c[1..].iter()).cloned().collect(),
+        ];
+        if counts.successors(&reversed).is_none() {
+            unseen.push(reversed);
+        }
+        for s in &[Smoothing::Backoff(0.5), Smoothing::KneserNey(0.75)] {
+            let estimator = smoothing::build(&counts, *s);
+            let path = dir.join(format!("{}.rgm", s)).to_string_lossy().to_string();
+            compiled::write(&*chain.store, &*estimator, None, &s.to_string(), &path).unwrap();
+            let file = compiled::open(&path).unwrap();
+            let table = file.forward();
+
+            for context in contexts.iter().chain(unseen.iter()) {
+                for w in words.iter() {
+                    let (want, got) = (estimator.prob(context, w), table.prob(context, w));
+                    assert!((want - got).abs() <= 1e-6 + want * 1e-5, "{} P({} | {:?}): {} != {}", s, w, context, got, want);
+                }
+            }
+
+            for context in contexts.iter() {
+                let mut want: Vec<&str> = counts.contexts[context].keys().map(|w| w.as_str()).collect();
+                want.sort();
+                let mut got = table.followers(context);
+                got.sort();
+                assert_eq!(want, got);
+            }
+            assert!(table.followers(&unseen[0]).is_empty());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{self, Error};
+use crate::model::smoothing::Smoothing;
+use crate::model::store;
+use crate::train::{data, grouping, train, DEFAULT_CHAR_ORDER};
+use crate::train::grouping::{Grouping, Scope};
+use crate::train::train::Persistent;
+
+// Trains a model on a corpus, into a db or, without one, into memory. The setters mirror the train
+// command's options and default the same way.
+pub struct Trainer {
+    corpus: String,
+    db: Option<String>,
+    // n-gram order, when training on characters instead of words
+    chars: Option<u32>,
+    grouping: Option<Grouping>,
+    max_order: Option<u32>,
+    scope: Scope,
+    // (min count, max vocabulary, use <unk> classes), see Persistent::prune
+    vocabulary: Option<(u64, Option<usize>, bool)>,
+    smoothing: Option<Smoothing>,
+    reverse: bool,
+    holdout: Option<f64>,
+}
+
+pub fn new(corpus: &str) -> Trainer {
+    return Trainer {
+        corpus: corpus.to_string(),
+        db: None,
+        chars: None,
+        grouping: None,
+        max_order: None,
+        scope: Scope::Corpus,
+        vocabulary: None,
+        smoothing: None,
+        reverse: false,
+        holdout: None,
+    };
+}
+
+impl Trainer {
+    pub fn db(mut self, db_path: &str) -> Trainer {
+        self.db = Some(db_path.to_string());
+        return self;
+    }
+
+    // a character model of the given n-gram order, for making up new words
+    pub fn chars(mut self, order: u32) -> Trainer {
+        self.chars = Some(order);
+        return self;
+    }
+
+    pub fn grouping(mut self, grouping: Grouping) -> Trainer {
+        self.grouping = Some(grouping);
+        return self;
+    }
+
+    // longest context any group gets
+    pub fn max_order(mut self, max_order: u32) -> Trainer {
+        self.max_order = Some(max_order);
+        return self;
+    }
+
+    pub fn scope(mut self, scope: Scope) -> Trainer {
+        self.scope = scope;
+        return self;
+    }
+
+    // replace words seen fewer than /min_count/ times, or outside the /max_vocab/ most frequent,
+    // with <unk> or one of its classes
+    pub fn vocabulary(mut self, min_count: u64, max_vocab: Option<usize>, classes: bool) -> Trainer {
+        self.vocabulary = Some((min_count, max_vocab, classes));
+        return self;
+    }
+
+    pub fn smoothing(mut self, smoothing: Smoothing) -> Trainer {
+        self.smoothing = Some(smoothing);
+        return self;
+    }
+
+    // train reverse chains as well, for generating towards an ending or around a keyword
+    pub fn reverse(mut self) -> Trainer {
+        self.reverse = true;
+        return self;
+    }
+
+    // fraction of the corpus files to keep out of training, to score the model on
+    pub fn holdout(mut self, fraction: f64) -> Trainer {
+        self.holdout = Some(fraction);
+        return self;
+    }
+
+    pub fn train(&self) -> Result<Persistent, Error> {
+        let path = self.corpus.as_str();
+        let order = self.chars.unwrap_or(DEFAULT_CHAR_ORDER);
+        if order < 2 {
+            return Err(Error::Parse("order must be at least 2".to_string()));
+        }
+        // every word needs at least a one word context
+        if self.max_order == Some(0) {
+            return Err(Error::Parse("max order must be at least 1".to_string()));
+        }
+        // past a half every file is held out, see data::split
+        if let Some(h) = self.holdout.filter(|h| !(*h > 0.0 && *h <= 0.5)) {
+            return Err(Error::Parse(format!("bad holdout {}, it must be above 0 and at most 0.5", h)));
+        }
+
+        // character models use a fixed order, a grouping would quietly replace it
+        if self.chars.is_some() && (self.grouping.is_some() || self.max_order.is_some()) {
+            return Err(Error::Parse("a character model has a fixed order, it can't take a grouping or max order too".to_string()));
+        }
+        let grouping = match (self.grouping.as_ref(), self.chars) {
+            (_, Some(_)) => Grouping::Fixed(order - 1),
+            (Some(g), None) => g.clone(),
+            (None, None) => Grouping::Log(grouping::DEFAULT_LOG_BASE),
+        };
+        let max_order = match (self.max_order, self.chars) {
+            (_, Some(_)) => order - 1,
+            (Some(m), None) => m,
+            (None, None) => grouping::DEFAULT_MAX_ORDER,
+        };
+
+        // walked so a store with a directory per source can be trained on, and its index skipped
+        let mut files = data::walk(Path::new(path)).map_err(error::io(&format!("couldnt open dir {}", path)))?;
+
+        // build a frequency map from all files
+        // build groups based on frequency map
+        // We know how many markov chains we want to use (args), this will be the top n most common that
+        // we found. Before we can train, we will need to build a lookup table for _word_ -> _group_
+        // without a db nothing is written, for trying out settings against the held out files
+        let mut chain = match self.db.as_ref() {
+            Some(db_path) => train::new(db_path)?,
+            None => train::with_store(Box::new(store::memory()))?,
+        };
+        chain.reset()?;
+        if self.chars.is_some() {
+            chain.chars()?;
+        }
+        if let Some((min_count, max_vocab, classes)) = self.vocabulary {
+            chain.prune(min_count, max_vocab, classes);
+        }
+        if let Some(s) = self.smoothing {
+            chain.smoothing(s)?;
+        }
+        if self.reverse {
+            chain.reverse()?;
+        }
+
+        // put aside every nth document for `evaluate`, sorted first so the split is the same each run
+        if let Some(fraction) = self.holdout {
+            files.sort();
+            let (held, rest) = data::split(files, fraction);
+
+            chain.holdout(&held)?;
+            files = rest;
+        }
+
+        // a source is whatever is directly under the training path, a file or a directory of them
+        let units: Vec<Vec<PathBuf>> = match self.scope {
+            Scope::Corpus => vec![files.clone()],
+            Scope::Document => files.iter().map(|f| vec![f.to_path_buf()]).collect(),
+            Scope::Source => {
+                let mut by: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+                files.iter().for_each(|f| {
+                    let top = f.strip_prefix(path).ok()
+                        .and_then(|r| r.components().next())
+                        .map(|c| Path::new(path).join(c))
+                        .unwrap_or(f.to_path_buf());
+                    by.entry(top).or_insert_with(Vec::new).push(f.to_path_buf());
+                });
+                by.into_iter().map(|(_, v)| v).collect()
+            }
+        };
+        chain.groups(&grouping, max_order, self.scope, &units)?;
+
+        // Now, we can train n markov chains simultaneously, deciding which one to put our words in
+        // based on their group. Each group is a separate markov chain trained on the same corpus.
+        // NB:
+        //   we will need to keep a stack of the last x words, where x == largest group
+        chain.train(&files)?;
+        return Ok(chain);
+    }
+}
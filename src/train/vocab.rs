@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::model::backoff::UNKNOWN;
+use crate::train::analyse::is_sentinel;
+
+// rare words kept per class for generation to put back in place of <unk>
+pub const MAX_RARE: usize = 1000;
+
+// Words left out of the vocabulary are replaced with <unk>, or with a class of it that says a little
+// about the word. Case and digits are gone by the time words are counted, so classes go by what's left
+// of the word's shape.
+pub fn class(word: &str, classes: bool) -> String {
+    if !classes {
+        return UNKNOWN.to_string();
+    }
+
+    let class = if word.contains('-') {
+        "hyphen"
+    } else if word.ends_with("'s") || word.ends_with("’s") {
+        "possessive"
+    } else if word.ends_with("ing") {
+        "ing"
+    } else if word.ends_with("ed") {
+        "ed"
+    } else if word.ends_with("ly") {
+        "ly"
+    } else if word.ends_with('s') {
+        "s"
+    } else {
+        return UNKNOWN.to_string();
+    };
+    return format!("<unk-{}>", class);
+}
+
+pub fn is_unknown(token: &str) -> bool {
+    return token == UNKNOWN || (token.starts_with("<unk-") && token.ends_with('>'));
+}
+
+pub struct Vocabulary {
+    pub keep: HashSet<String>,
+    pub classes: bool,
+}
+
+impl Vocabulary {
+    // swap every word outside the vocabulary for its <unk> class
+    pub fn map(&self, tokens: Vec<String>) -> Vec<String> {
+        return tokens.into_iter()
+            .map(|t| if is_sentinel(&t) || self.keep.contains(&t) { t } else { class(&t, self.classes) })
+            .collect();
+    }
+}
+
+// Words seen at least /min_count/ times, only the /max_vocab/ most frequent of those if given. Ties at
+// the cut off are broken alphabetically so the same corpus gives the same vocabulary.
+pub fn select(counts: &HashMap<String, u64>, min_count: u64, max_vocab: Option<usize>) -> HashSet<String> {
+    let mut words: Vec<(&String, &u64)> = counts.iter()
+        .filter(|(w, c)| **c >= min_count || is_sentinel(w))
+        .collect();
+    words.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
+
+    // sentinels don't take up room in the vocabulary
+    let limit = max_vocab.unwrap_or(std::usize::MAX);
+    let mut kept = 0;
+    let mut result = HashSet::new();
+    for (w, _) in words {
+        if is_sentinel(w) {
+            result.insert(w.to_string());
+        } else if kept < limit {
+            result.insert(w.to_string());
+            kept += 1;
+        }
+    }
+
+    return result;
+}
+
+// the most frequent of the words that didn't make it into the vocabulary, by class
+pub fn rare(counts: &HashMap<String, u64>, vocabulary: &Vocabulary) -> HashMap<String, Vec<String>> {
+    let mut left: Vec<(&String, &u64)> = counts.iter()
+        .filter(|(w, _)| !vocabulary.keep.contains(*w) && !is_sentinel(w))
+        .collect();
+    left.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then(k1.cmp(k2)));
+
+    let mut result: HashMap<String, Vec<String>> = HashMap::new();
+    for (w, _) in left {
+        let words = result.entry(class(w, vocabulary.classes)).or_insert_with(Vec::new);
+        if words.len() < MAX_RARE {
+            words.push(w.to_string());
+        }
+    }
+
+    return result;
+}